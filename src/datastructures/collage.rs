@@ -0,0 +1,146 @@
+use image::{DynamicImage, Rgba};
+use serde::Deserialize;
+use tokio::task::spawn_blocking;
+
+use super::{
+    image::{ImageJson, Sizing},
+    upload::{ImageInputs, WithUploads},
+};
+use crate::{
+    errors::Errors,
+    imagelib::{
+        collage::{self, Cell},
+        fillcolor::parse_hex_color,
+        fit::{Fit, Gravity},
+    },
+    state::serverstate::ServerState,
+};
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Layout {
+    /// Filled row by row, missing dimensions are derived from the image count
+    Grid {
+        rows: Option<u32>,
+        columns: Option<u32>,
+    },
+    Horizontal,
+    Vertical,
+    /// First image large on the left, the rest stacked on the right
+    Feature,
+    /// First image across the top, the rest in a row below
+    Banner,
+}
+
+#[derive(Deserialize)]
+pub struct CollageJson {
+    images: Vec<ImageJson>,
+    #[serde(default = "default_value::layout")]
+    layout: Layout,
+    #[serde(default = "default_value::cell_size")]
+    cell_size: (u32, u32),
+    #[serde(default)]
+    gap: u32,
+    /// Hex colour, transparent when missing
+    background: Option<String>,
+    #[serde(default = "default_value::fit")]
+    fit: Fit,
+}
+
+impl CollageJson {
+    /// Canvas size and the cell of every input image.
+    fn cells(&self) -> Result<((u32, u32), Vec<Cell>), Errors> {
+        let count = self.images.len() as u32;
+        Ok(match self.layout {
+            Layout::Grid { rows, columns } => {
+                let columns = match (rows, columns) {
+                    (_, Some(0)) | (Some(0), _) => {
+                        return Err(Errors::InvalidInput(
+                            "Grid rows and columns must be at least 1".into(),
+                        ))
+                    }
+                    (Some(rows), Some(columns))
+                        if (rows as u64) * (columns as u64) < count as u64 =>
+                    {
+                        return Err(Errors::InvalidInput(format!(
+                            "A {}x{} grid can't hold {} images",
+                            rows, columns, count
+                        )))
+                    }
+                    (_, Some(columns)) => columns,
+                    (Some(rows), None) => count.div_ceil(rows),
+                    (None, None) => (count as f32).sqrt().ceil() as u32,
+                };
+                collage::grid(count, columns.min(count), self.cell_size, self.gap)
+            }
+            Layout::Horizontal => collage::grid(count, count, self.cell_size, self.gap),
+            Layout::Vertical => collage::grid(count, 1, self.cell_size, self.gap),
+            Layout::Feature => collage::feature(count, self.cell_size, self.gap),
+            Layout::Banner => collage::banner(count, self.cell_size, self.gap),
+        })
+    }
+
+    pub async fn process(self, state: &'static ServerState) -> Result<DynamicImage, Errors> {
+        let max = state.config.output_image_max_size;
+        if self.images.is_empty() || self.images.len() > state.config.collage_max_images {
+            return Err(Errors::InvalidInput(format!(
+                "A collage takes between 1 and {} images",
+                state.config.collage_max_images
+            )));
+        }
+        state.config.validate_output_size(self.cell_size)?;
+        if self.gap > max {
+            return Err(Errors::InvalidInput(format!("Gap must be at most {}", max)));
+        }
+        let background = Rgba(match &self.background {
+            Some(hex) => parse_hex_color(hex)?,
+            None => [0; 4],
+        });
+
+        let (canvas, cells) = self.cells()?;
+        state.config.validate_output_size(canvas)?;
+
+        let sizings = cells.iter().map(|cell| Sizing::Custom {
+            size: (cell.width, cell.height),
+            fit: self.fit,
+            gravity: Gravity::Center,
+            background,
+        });
+        let images = ImageJson::to_images(self.images.iter().zip(sizings), state).await?;
+
+        spawn_blocking(move || {
+            let images: Vec<_> = cells.into_iter().zip(images).collect();
+            Ok(DynamicImage::ImageRgba8(collage::compose(
+                canvas, background, &images,
+            )))
+        })
+        .await?
+    }
+}
+
+impl ImageInputs for CollageJson {
+    fn images_mut(&mut self) -> Vec<&mut ImageJson> {
+        self.images.iter_mut().collect()
+    }
+}
+
+pub type CollageInput<'a> = Result<WithUploads<CollageJson>, Errors>;
+
+mod default_value {
+    use super::{Fit, Layout};
+
+    pub fn layout() -> Layout {
+        Layout::Grid {
+            rows: None,
+            columns: None,
+        }
+    }
+
+    pub fn cell_size() -> (u32, u32) {
+        (256, 256)
+    }
+
+    pub fn fit() -> Fit {
+        Fit::Cover
+    }
+}
@@ -0,0 +1,126 @@
+use image::RgbaImage;
+use rocket::serde::json::{Error as JsonError, Json};
+use serde::Deserialize;
+
+use crate::{
+    errors::Errors,
+    imagelib::{gradient, pattern},
+};
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GradientKind {
+    Linear,
+    Radial,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Gradient {
+    #[serde(default = "default_value::gradient_kind")]
+    kind: GradientKind,
+    /// `(offset, [r, g, b, a])` pairs, offsets in `0.0..=1.0`
+    stops: Vec<(f32, [u8; 4])>,
+    /// Degrees clockwise from left→right, only used by linear gradients
+    #[serde(default)]
+    angle: f32,
+}
+
+impl Gradient {
+    pub fn validate(&self) -> Result<(), Errors> {
+        if self.stops.len() < 2 {
+            return Err(Errors::InvalidInput(
+                "A gradient needs at least two color stops".into(),
+            ));
+        }
+        if self
+            .stops
+            .iter()
+            .any(|(offset, _)| !(0.0..=1.0).contains(offset))
+        {
+            return Err(Errors::InvalidInput(
+                "Gradient stop offsets must be between 0 and 1".into(),
+            ));
+        }
+        if !self.angle.is_finite() {
+            return Err(Errors::InvalidInput("Invalid gradient angle".into()));
+        }
+        Ok(())
+    }
+
+    pub fn render(&self, size: (u32, u32)) -> RgbaImage {
+        let mut stops = self.stops.clone();
+        stops.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        match self.kind {
+            GradientKind::Linear => gradient::linear_gradient(&stops, self.angle, size),
+            GradientKind::Radial => gradient::radial_gradient(&stops, size),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Pattern {
+    Checkerboard {
+        cell_size: u32,
+        #[serde(default = "default_value::pattern_colors")]
+        colors: [[u8; 4]; 2],
+    },
+    Stripes {
+        width: u32,
+        #[serde(default)]
+        angle: f32,
+        #[serde(default = "default_value::pattern_colors")]
+        colors: [[u8; 4]; 2],
+    },
+    Noise {
+        seed: u64,
+        #[serde(default)]
+        monochrome: bool,
+    },
+}
+
+impl Pattern {
+    pub fn validate(&self) -> Result<(), Errors> {
+        match self {
+            Self::Checkerboard { cell_size: 0, .. } => Err(Errors::InvalidInput(
+                "Checkerboard cell size must be above 0".into(),
+            )),
+            Self::Stripes { width: 0, .. } => {
+                Err(Errors::InvalidInput("Stripe width must be above 0".into()))
+            }
+            Self::Stripes { angle, .. } if !angle.is_finite() => {
+                Err(Errors::InvalidInput("Invalid stripe angle".into()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn render(&self, size: (u32, u32)) -> RgbaImage {
+        match self {
+            Self::Checkerboard { cell_size, colors } => {
+                pattern::checkerboard(*colors, *cell_size, size)
+            }
+            Self::Stripes {
+                width,
+                angle,
+                colors,
+            } => pattern::stripes(*colors, *width, *angle, size),
+            Self::Noise { seed, monochrome } => pattern::noise(*seed, *monochrome, size),
+        }
+    }
+}
+
+pub type GradientInput<'a> = Result<Json<Gradient>, JsonError<'a>>;
+pub type PatternInput<'a> = Result<Json<Pattern>, JsonError<'a>>;
+
+mod default_value {
+    use super::GradientKind;
+
+    pub fn gradient_kind() -> GradientKind {
+        GradientKind::Linear
+    }
+
+    pub fn pattern_colors() -> [[u8; 4]; 2] {
+        [[0, 0, 0, 255], [255, 255, 255, 255]]
+    }
+}
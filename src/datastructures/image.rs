@@ -1,19 +1,47 @@
-use std::io::Cursor;
+use std::{borrow::Cow, io::Cursor, time::Duration};
 
-use image::{io::Reader, DynamicImage, GenericImageView};
-use rocket::serde::json::{Error as JsonError, Json};
+use image::{io::Reader, DynamicImage, GenericImageView, Rgba};
+use rocket::futures::{future::try_join_all, stream, StreamExt, TryStreamExt};
 use serde::Deserialize;
-use tokio::task::spawn_blocking;
+use tokio::{task::spawn_blocking, time::timeout};
 
-use crate::{errors::Errors, imagelib::fillcolor::fill_color, state::serverstate::ServerState};
+use super::{
+    generator::{Gradient, Pattern},
+    upload::ImageBody,
+};
+use crate::{
+    errors::Errors,
+    imagelib::{
+        fillcolor::fill_color,
+        fit::{fit as fit_image, Fit, Gravity},
+        info::header_dimensions,
+    },
+    state::{download, serverstate::ServerState},
+};
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum ImageJson {
+    /// A user's avatar, or their avatar in a guild when `guild_id` is set.
+    /// A null hash is the default avatar
     DiscordProfile {
+        id: u64,
+        hash: Option<String>,
+        guild_id: Option<u64>,
+    },
+    DiscordBanner {
+        id: u64,
+        hash: String,
+    },
+    DiscordGuildIcon {
         id: u64,
         hash: String,
     },
+    DiscordEmoji {
+        id: u64,
+        #[serde(default)]
+        animated: bool,
+    },
     GithubProfile {
         username: String,
     },
@@ -26,16 +54,84 @@ pub enum ImageJson {
         id: String,
         subdomain: String,
     },
+    /// Any public http(s) URL, when enabled
+    Url(String),
     Color(u8, u8, u8),
+    Gradient(Gradient),
+    Pattern(Pattern),
     Base64(String),
-    File(String),
+    /// A file of the multipart body by field name, see [`WithUploads`](super::upload::WithUploads)
+    Upload(String),
+    /// Uploaded as a raw body or multipart file, see [`ImageBody`]
+    #[serde(skip)]
+    Bytes(Vec<u8>),
+    /// A file inside one of the configured asset roots
+    File {
+        root: String,
+        path: String,
+    },
+}
+
+/// How [`ImageJson::to_image`] sizes the decoded image.
+#[derive(Debug, Clone, Copy)]
+pub enum Sizing {
+    /// Keep the source dimensions, generated images are 1024×1024
+    Native,
+    /// Stretch to exactly `width × height`
+    Exact(u32, u32),
+    /// Scale to fit within `width × height`, preserving aspect ratio
+    Fit(u32, u32),
+    /// Scale to cover `width × height`, cropping the centred overflow
+    Cover(u32, u32),
+    /// Any fit mode, anchor and padding colour
+    Custom {
+        size: (u32, u32),
+        fit: Fit,
+        gravity: Gravity,
+        background: Rgba<u8>,
+    },
+}
+
+impl Sizing {
+    /// Fits into a `size` square, 0 keeps the native size.
+    #[inline]
+    pub fn square(size: u32) -> Self {
+        match size {
+            0 => Self::Native,
+            size => Self::Fit(size, size),
+        }
+    }
+
+    /// Size requested from remote sources, 0 for their default size.
+    fn fetch_size(self) -> u32 {
+        match self.to_custom() {
+            Self::Custom { size, .. } => size.0.max(size.1),
+            _ => 0,
+        }
+    }
+
+    /// Expresses the shorthand variants as `Custom`.
+    fn to_custom(self) -> Self {
+        let custom = |w, h, fit| Self::Custom {
+            size: (w, h),
+            fit,
+            gravity: Gravity::Center,
+            background: Rgba([0; 4]),
+        };
+        match self {
+            Self::Exact(w, h) => custom(w, h, Fit::Fill),
+            Self::Fit(w, h) => custom(w, h, Fit::Inside),
+            Self::Cover(w, h) => custom(w, h, Fit::Cover),
+            sizing => sizing,
+        }
+    }
 }
 
 #[derive(Deserialize)]
 #[serde(untagged)]
 enum GithubContentsResponse {
     GithubContents {
-        size: u32,
+        size: usize,
         #[serde(rename = "type")]
         type_: String,
         download_url: String,
@@ -51,16 +147,55 @@ struct GithubError {
     code: String,
 }
 
+/// Discord hashes of animated images start with `a_`
+fn is_animated(hash: &str) -> bool {
+    hash.starts_with("a_")
+}
+
+/// CDN URL of a Discord image, animated ones as GIF. Discord only serves
+/// power of two sizes from 16 to 4096, the requested size is rounded up.
+fn discord_url(path: &str, animated: bool, size: u32) -> String {
+    let size = if size == 0 {
+        1024
+    } else {
+        size.clamp(16, 4096).next_power_of_two()
+    };
+    format!(
+        "https://cdn.discordapp.com/{}.{}?size={}",
+        path,
+        if animated { "gif" } else { "png" },
+        size
+    )
+}
+
 impl ImageJson {
     fn url(&self, size: u32) -> Result<String, Errors> {
         match self {
-            Self::DiscordProfile { id, hash } => Ok(format!(
-                "https://cdn.discordapp.com/avatars/{}/{}.{}?size={}",
+            Self::DiscordProfile { hash: None, .. } => Ok(self.default_url().unwrap()),
+            Self::DiscordProfile {
                 id,
-                hash,
-                "png",
-                if size == 0 { 1024 } else { size }
+                hash: Some(hash),
+                guild_id,
+            } => {
+                let path = match guild_id {
+                    Some(guild_id) => format!("guilds/{}/users/{}/avatars/{}", guild_id, id, hash),
+                    None => format!("avatars/{}/{}", id, hash),
+                };
+                Ok(discord_url(&path, is_animated(hash), size))
+            }
+            Self::DiscordBanner { id, hash } => Ok(discord_url(
+                &format!("banners/{}/{}", id, hash),
+                is_animated(hash),
+                size,
+            )),
+            Self::DiscordGuildIcon { id, hash } => Ok(discord_url(
+                &format!("icons/{}/{}", id, hash),
+                is_animated(hash),
+                size,
             )),
+            Self::DiscordEmoji { id, animated } => {
+                Ok(discord_url(&format!("emojis/{}", id), *animated, size))
+            }
             Self::GithubProfile { username } => Ok(format!(
                 "https://github.com/{}.png{}",
                 username,
@@ -87,70 +222,192 @@ impl ImageJson {
                 };
                 Ok(format!("https://{}imgur.com/{}{}.png", subdomain, id, size))
             }
-            Self::Base64(..) | Self::GithubAsset { .. } | Self::Color(..) => unreachable!(),
-            Self::File(..) => unreachable!(),
+            Self::Base64(..) | Self::Upload(..) | Self::Bytes(..) => unreachable!(),
+            Self::GithubAsset { .. } | Self::Color(..) => unreachable!(),
+            Self::Gradient(..) | Self::Pattern(..) => unreachable!(),
+            Self::File { .. } | Self::Url(..) => unreachable!(),
+        }
+    }
+
+    /// The provider's placeholder for users or images that don't exist,
+    /// `None` for assets that have none.
+    fn default_url(&self) -> Option<String> {
+        match self {
+            Self::DiscordProfile { id, .. } => Some(format!(
+                "https://cdn.discordapp.com/embed/avatars/{}.png",
+                (id >> 22) % 6
+            )),
+            Self::GithubProfile { username } => {
+                Some(format!("https://github.com/identicons/{}.png", username))
+            }
+            Self::Imgur { .. } => Some("https://i.imgur.com/removed.png".into()),
+            _ => None,
         }
     }
-    async fn to_vec(&self, size: u32, state: &ServerState) -> Result<Vec<u8>, Errors> {
+
+    fn not_found_message(&self) -> String {
+        match self {
+            Self::DiscordProfile {
+                id,
+                hash: Some(hash),
+                ..
+            } => format!("Discord user {} has no avatar {:?}", id, hash),
+            Self::DiscordProfile { id, .. } => format!("Discord user {} not found", id),
+            Self::DiscordBanner { id, hash } => {
+                format!("Discord user {} has no banner {:?}", id, hash)
+            }
+            Self::DiscordGuildIcon { id, hash } => {
+                format!("Discord guild {} has no icon {:?}", id, hash)
+            }
+            Self::DiscordEmoji { id, .. } => format!("Discord emoji {} not found", id),
+            Self::GithubProfile { username } => format!("GitHub user {:?} not found", username),
+            Self::GithubAsset { owner, repo, path } => {
+                format!("File {:?} not found in {}/{}", path, owner, repo)
+            }
+            Self::Imgur { id, .. } => format!("Imgur image {:?} not found", id),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Fetches the encoded bytes of the image without decoding them.
+    pub async fn to_vec(&self, size: u32, state: &ServerState) -> Result<Vec<u8>, Errors> {
         match self {
             Self::Base64(text) => base64::decode(text)
                 .map_err(|_| Errors::InvalidInput("Invalid base64 string provided".into())),
+            Self::Bytes(bytes) => Ok(bytes.clone()),
+            // Replaced by the file when the body was a multipart form
+            Self::Upload(name) => Err(Errors::InvalidInput(format!(
+                "Upload {:?} needs a multipart/form-data body with a file of that name",
+                name
+            ))),
             Self::GithubAsset { owner, repo, path } => {
-                self.get_github_asset(owner, repo, path, state.client())
-                    .await
+                self.get_github_asset(owner, repo, path, state).await
+            }
+            Self::Imgur { .. }
+            | Self::GithubProfile { .. }
+            | Self::DiscordProfile { .. }
+            | Self::DiscordBanner { .. }
+            | Self::DiscordGuildIcon { .. }
+            | Self::DiscordEmoji { .. } => {
+                let sources = &state.config.sources;
+                let limits = match self {
+                    Self::GithubProfile { .. } => &sources.github,
+                    Self::Imgur { .. } => &sources.imgur,
+                    _ => &sources.discord,
+                };
+                match (
+                    state.fetch(&self.url(size)?, size, limits).await,
+                    self.default_url(),
+                ) {
+                    (Err(Errors::NotFound(..)), Some(default_url)) if limits.default_avatar => {
+                        state.fetch(&default_url, size, limits).await
+                    }
+                    (Err(Errors::NotFound(..)), _) => {
+                        Err(Errors::NotFound(self.not_found_message()))
+                    }
+                    (result, _) => result,
+                }
             }
-            Self::Imgur { .. } | Self::GithubProfile { .. } | Self::DiscordProfile { .. } => {
+            Self::Url(url) => {
+                if !state.config.allow_url_input {
+                    return Err(Errors::InvalidInput("URL input is disabled.".into()));
+                }
+                download::fetch_public_url(url, &state.config).await
+            }
+            Self::File { root, path } => {
+                let path = state.config.asset_path(root, path).await?;
                 Ok(state
-                    .client()
-                    .get(self.url(size)?)
-                    .send()
-                    .await?
-                    .bytes()
+                    .cache
+                    .get_image(&path.to_string_lossy())
                     .await?
                     .to_vec())
             }
-            Self::File(filename) => {
-                if !state.config.allow_local_file_input {
-                    return Err(Errors::InvalidInput("Local file input is disabled.".into()));
-                }
-                Ok(state.cache.get_image(filename).await.unwrap().to_vec())
-            }
-            Self::Color(..) => unreachable!(),
+            Self::Color(..) | Self::Gradient(..) | Self::Pattern(..) => Err(Errors::InvalidInput(
+                "Generated images have no source data".into(),
+            )),
         }
     }
 
+    /// Fetches the source bytes without decoding them, generated images are
+    /// left for [`Resolved::into_image`] to render. The dimensions are read
+    /// from the header and checked against the decode limits.
+    pub async fn resolve(
+        &self,
+        sizing: Sizing,
+        state: &ServerState,
+    ) -> Result<Resolved<'_>, Errors> {
+        let (bytes, source_size) = match self {
+            Self::Color(..) | Self::Gradient(..) | Self::Pattern(..) => (None, None),
+            _ => {
+                let bytes = self.to_vec(sizing.fetch_size(), state).await?;
+                let size = header_dimensions(&bytes)?;
+                state.config.check_decode_size(size)?;
+                (Some(bytes), Some(size))
+            }
+        };
+
+        // Decoded as RGBA8 at most, sources also need their resized copy
+        let rgba_bytes = |(width, height): (u32, u32)| width as u64 * height as u64 * 4;
+        let target_size = match sizing.to_custom() {
+            Sizing::Custom { size, .. } => Some(size),
+            _ => None,
+        };
+        let memory = match source_size {
+            Some(size) => rgba_bytes(size) + target_size.map_or(0, rgba_bytes),
+            None => rgba_bytes(target_size.unwrap_or((1024, 1024))),
+        };
+        state.config.check_memory_budget(memory)?;
+
+        Ok(Resolved {
+            image: self,
+            sizing,
+            bytes,
+            memory,
+        })
+    }
+
     pub async fn to_image(
         &self,
-        size: u32,
+        sizing: Sizing,
         state: &'static ServerState,
     ) -> Result<DynamicImage, Errors> {
-        let mut image = match self {
-            Self::Color(r, g, b) => {
-                let size = if size == 0 { 1024 } else { size };
-                let (r, g, b) = (*r, *g, *b);
-                spawn_blocking(move || DynamicImage::ImageRgb8(fill_color([r, g, b], (size, size))))
-                    .await?
-            }
-
-            _ => {
-                let bytes = self.to_vec(size, state).await?;
+        self.resolve(sizing, state).await?.into_image(state).await
+    }
 
-                spawn_blocking(move || {
-                    let reader = Reader::new(Cursor::new(&bytes));
-                    reader.with_guessed_format()?.decode()
-                })
-                .await??
-            }
-        };
+    /// Resolves several images concurrently, at most `fetch_concurrency` at
+    /// a time and within `fetch_deadline_ms`. The output keeps the input order.
+    pub async fn resolve_all<'a>(
+        inputs: impl IntoIterator<Item = (&'a ImageJson, Sizing)>,
+        state: &'static ServerState,
+    ) -> Result<Vec<Resolved<'a>>, Errors> {
+        // Collected first, a closure held across the await trips up the Send checks
+        let fetches: Vec<_> = inputs
+            .into_iter()
+            .map(|(image, sizing)| image.resolve(sizing, state))
+            .collect();
+        let resolved = stream::iter(fetches)
+            .buffered(state.config.fetch_concurrency.max(1))
+            .try_collect();
+        let resolved: Vec<Resolved> = timeout(
+            Duration::from_millis(state.config.fetch_deadline_ms),
+            resolved,
+        )
+        .await
+        .map_err(|_| Errors::Timeout)??;
 
-        // Resize if required
-        if size != 0 && (image.width() != size || image.height() != size) {
-            image =
-                spawn_blocking(move || image.resize(size, size, state.config.resize_filtertype()))
-                    .await?;
-        }
+        state
+            .config
+            .check_memory_budget(resolved.iter().map(|source| source.memory).sum())?;
+        Ok(resolved)
+    }
 
-        Ok(image)
+    /// [`ImageJson::resolve_all`] followed by decoding and sizing every image.
+    pub async fn to_images<'a>(
+        inputs: impl IntoIterator<Item = (&'a ImageJson, Sizing)>,
+        state: &'static ServerState,
+    ) -> Result<Vec<DynamicImage>, Errors> {
+        let resolved = Self::resolve_all(inputs, state).await?;
+        try_join_all(resolved.into_iter().map(|source| source.into_image(state))).await
     }
 
     async fn get_github_asset(
@@ -158,21 +415,27 @@ impl ImageJson {
         owner: &str,
         repo: &str,
         path: &str,
-        client: &reqwest::Client,
+        state: &ServerState,
     ) -> Result<Vec<u8>, Errors> {
-        match client
+        let limits = &state.config.sources.github;
+        let response = state
+            .client()
             .get(format!(
                 "https://api.github.com/repos/{}/{}/contents/{}",
                 owner, repo, path
             ))
+            .timeout(limits.timeout())
             .send()
-            .await?
-            .json::<GithubContentsResponse>()
-            .await?
-        {
+            .await?;
+        match download::check_status(&response) {
+            Err(Errors::NotFound(..)) => return Err(Errors::NotFound(self.not_found_message())),
+            result => result?,
+        }
+        let contents = response.json::<GithubContentsResponse>().await?;
+        match contents {
             GithubContentsResponse::GithubError { message, errors } => {
                 if errors.iter().any(|e| e.code == "too_large") {
-                    return Err(Errors::InvalidInput("File too large".into()));
+                    return Err(Errors::TooLarge(limits.max_bytes));
                 }
                 return Err(Errors::InvalidInput(format!(
                     "Message from github: {}",
@@ -190,22 +453,111 @@ impl ImageJson {
                         type_
                     )));
                 }
-                if size > 500000 {
-                    return Err(Errors::InvalidInput(format!(
-                        "File size too large, must be below {}",
-                        500000
-                    )));
+                if size > limits.max_bytes {
+                    return Err(Errors::TooLarge(limits.max_bytes));
                 }
-                Ok(client
-                    .get(download_url)
-                    .send()
+                state.fetch(&download_url, 0, limits).await
+            }
+        }
+    }
+}
+
+/// An input with its source bytes fetched, ready to be decoded and sized.
+pub struct Resolved<'a> {
+    image: &'a ImageJson,
+    sizing: Sizing,
+    /// `None` for generated images
+    bytes: Option<Vec<u8>>,
+    /// Estimated bytes taken once decoded and sized
+    memory: u64,
+}
+
+impl Resolved<'_> {
+    /// Identifies the input in cache keys: the source bytes, or the
+    /// description of a generated image.
+    pub fn fingerprint(&self) -> Cow<'_, [u8]> {
+        match &self.bytes {
+            Some(bytes) => Cow::Borrowed(bytes),
+            None => Cow::Owned(format!("{:?}", self.image).into_bytes()),
+        }
+    }
+
+    /// Decodes or renders the image at its native size, generated images
+    /// are rendered at `generate_size`.
+    async fn decode(
+        self,
+        generate_size: (u32, u32),
+        state: &ServerState,
+    ) -> Result<DynamicImage, Errors> {
+        Ok(match (self.image, self.bytes) {
+            (ImageJson::Color(r, g, b), _) => {
+                let (r, g, b) = (*r, *g, *b);
+                spawn_blocking(move || {
+                    DynamicImage::ImageRgb8(fill_color([r, g, b], generate_size))
+                })
+                .await?
+            }
+            (ImageJson::Gradient(gradient), _) => {
+                gradient.validate()?;
+                let gradient = gradient.clone();
+                spawn_blocking(move || DynamicImage::ImageRgba8(gradient.render(generate_size)))
                     .await?
-                    .bytes()
+            }
+            (ImageJson::Pattern(pattern), _) => {
+                pattern.validate()?;
+                let pattern = pattern.clone();
+                spawn_blocking(move || DynamicImage::ImageRgba8(pattern.render(generate_size)))
                     .await?
-                    .to_vec())
             }
+            // Local files are usually template assets, decoded once and shared
+            (ImageJson::File { root, path }, _) => {
+                let path = state.config.asset_path(root, path).await?;
+                state
+                    .cache
+                    .get_decoded_owned(&path.to_string_lossy())
+                    .await?
+            }
+            (_, bytes) => {
+                let bytes = bytes.unwrap_or_default();
+                spawn_blocking(move || {
+                    let reader = Reader::new(Cursor::new(&bytes));
+                    reader.with_guessed_format()?.decode()
+                })
+                .await??
+            }
+        })
+    }
+
+    pub async fn into_image(self, state: &'static ServerState) -> Result<DynamicImage, Errors> {
+        let (size, fit, gravity, background) = match self.sizing.to_custom() {
+            Sizing::Custom {
+                size,
+                fit,
+                gravity,
+                background,
+            } => (size, fit, gravity, background),
+            _ => return self.decode((1024, 1024), state).await,
+        };
+
+        let image = self.decode(size, state).await?;
+        let (width, height) = image.dimensions();
+        let already_sized = match fit {
+            // Fitting inside leaves one side touching the box
+            Fit::Inside => {
+                width <= size.0 && height <= size.1 && (width == size.0 || height == size.1)
+            }
+            _ => (width, height) == size,
+        };
+        if already_sized {
+            return Ok(image);
         }
+
+        let filter = state.config.resize_filtertype();
+        Ok(
+            spawn_blocking(move || fit_image(&image, size, fit, gravity, background, filter))
+                .await?,
+        )
     }
 }
 
-pub type Image<'a> = Result<Json<ImageJson>, JsonError<'a>>;
+pub type Image<'a> = Result<ImageBody, Errors>;
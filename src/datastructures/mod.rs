@@ -1,2 +1,5 @@
+pub mod collage;
+pub mod generator;
 pub mod image;
 pub mod template;
+pub mod upload;
@@ -1,12 +1,16 @@
-use std::io::Cursor;
-
-use image::{io::Reader as ImageReader, DynamicImage, Rgba};
-use rocket::serde::json::{Error as JsonError, Json};
+use image::{DynamicImage, Rgba};
+use rocket::futures::future::try_join_all;
 use serde::Deserialize;
 use tokio::task::spawn_blocking;
 
-use super::image::ImageJson;
-use crate::{errors::Errors, state::serverstate::ServerState};
+use super::{
+    image::{ImageJson, Resolved, Sizing},
+    upload::{ImageInputs, WithUploads},
+};
+use crate::{
+    errors::Errors,
+    state::{asset_cache::stamp, render_cache::RenderKey, serverstate::ServerState},
+};
 
 #[derive(Deserialize)]
 pub struct TemplateInputJson {
@@ -90,13 +94,12 @@ pub struct Template {
 }
 
 impl Template {
-    pub async fn process(
-        &'static self,
+    /// Fetches the overlay inputs without decoding them.
+    pub async fn resolve<'a>(
+        &self,
         state: &'static ServerState,
-        input: TemplateInputJson,
-    ) -> Result<DynamicImage, Errors> {
-        let cursor = Cursor::new(state.cache.get_image(&self.startfile).await?.to_vec());
-
+        input: &'a TemplateInputJson,
+    ) -> Result<Vec<Resolved<'a>>, Errors> {
         let overlays = self
             .operations
             .iter()
@@ -106,21 +109,56 @@ impl Template {
                 _ => unreachable!(),
             });
 
-        let mut overlay_layers = vec![];
-        for (overlay, image) in overlays.zip(input.images.iter()) {
-            overlay_layers.push(image.to_image(overlay.input_size, state).await?);
+        ImageJson::resolve_all(
+            overlays
+                .zip(input.images.iter())
+                .map(|(overlay, image)| (image, Sizing::square(overlay.input_size))),
+            state,
+        )
+        .await
+    }
+
+    /// Includes the on-disk stamp of the start file and fonts, which the asset
+    /// cache also reloads them on, so replacing them on disk doesn't keep
+    /// serving earlier renders.
+    pub fn render_key(
+        &self,
+        state: &ServerState,
+        input: &TemplateInputJson,
+        sources: &[Resolved<'_>],
+    ) -> RenderKey {
+        let mut key = state
+            .render_cache
+            .key("/template")
+            .param("name", &self.name)
+            .param("texts", &input.texts);
+        let assets =
+            std::iter::once(self.startfile()).chain(self.fonts(&state.config.default_font));
+        for asset in assets {
+            key = key.param(asset, stamp(asset));
         }
+        sources.iter().fold(key, |key, source| key.input(source))
+    }
 
-        spawn_blocking(move || {
-            let mut img = ImageReader::new(cursor).with_guessed_format()?.decode()?;
+    pub async fn process(
+        &'static self,
+        state: &'static ServerState,
+        texts: Vec<String>,
+        sources: Vec<Resolved<'_>>,
+    ) -> Result<DynamicImage, Errors> {
+        let mut img = state.cache.get_decoded_owned(&self.startfile).await?;
+
+        let overlay_layers =
+            try_join_all(sources.into_iter().map(|source| source.into_image(state))).await?;
 
+        spawn_blocking(move || {
             let mut text_index = 0;
             let mut overlay_index = 0;
 
             for op in self.operations.iter() {
                 match op {
                     Operation::DrawText(dt) => {
-                        img = dt.process(img, state, &input.texts[text_index])?;
+                        img = dt.process(img, state, &texts[text_index])?;
                         text_index += 1;
                     }
                     Operation::Overlay(overlay) => {
@@ -134,6 +172,18 @@ impl Template {
         .await?
     }
 
+    /// Names of the fonts drawn with, `default_font` stands in for unset ones.
+    pub fn fonts<'a>(&'a self, default_font: &'a str) -> impl Iterator<Item = &'a str> {
+        self.operations.iter().filter_map(move |o| match o {
+            Operation::DrawText(dt) => Some(dt.font.as_deref().unwrap_or(default_font)),
+            _ => None,
+        })
+    }
+
+    pub fn startfile(&self) -> &str {
+        &self.startfile
+    }
+
     pub fn validate(&self, input: &TemplateInputJson) -> Result<(), Errors> {
         let expected = self
             .operations
@@ -162,7 +212,13 @@ impl Template {
     }
 }
 
-pub type TemplateInput<'a> = Result<Json<TemplateInputJson>, JsonError<'a>>;
+impl ImageInputs for TemplateInputJson {
+    fn images_mut(&mut self) -> Vec<&mut ImageJson> {
+        self.images.iter_mut().collect()
+    }
+}
+
+pub type TemplateInput<'a> = Result<WithUploads<TemplateInputJson>, Errors>;
 
 mod default_value {
     pub fn color_4() -> [u8; 4] {
@@ -0,0 +1,203 @@
+use std::{collections::HashMap, ops::Deref};
+
+use rocket::{
+    data::{self, Data, FromData, Limits},
+    form::{self, DataField, Form, FromForm, ValueField},
+    fs::TempFile,
+    http::{ContentType, Status},
+    outcome::Outcome,
+    serde::json::Json,
+    Request,
+};
+use serde::de::DeserializeOwned;
+
+use super::image::ImageJson;
+use crate::errors::Errors;
+
+/// Request bodies with image inputs that can be uploaded as files.
+pub trait ImageInputs {
+    fn images_mut(&mut self) -> Vec<&mut ImageJson>;
+}
+
+/// An image input from a JSON body, a raw `image/*` body or the file of a
+/// `multipart/form-data` body. Uploads are capped by the `image`
+/// data limit, multipart ones by `file` and `data-form`.
+#[derive(Debug)]
+pub struct ImageBody(ImageJson);
+
+#[rocket::async_trait]
+impl<'r> FromData<'r> for ImageBody {
+    type Error = Errors;
+
+    async fn from_data(req: &'r Request<'_>, data: Data<'r>) -> data::Outcome<'r, Self> {
+        let content_type = req.content_type().cloned().unwrap_or(ContentType::JSON);
+        if content_type.top() == "image" {
+            let limit = req.limits().get("image").unwrap_or(Limits::FILE);
+            return match data.open(limit).into_bytes().await {
+                Ok(bytes) if bytes.is_complete() => {
+                    Outcome::Success(Self(ImageJson::Bytes(bytes.into_inner())))
+                }
+                Ok(_) => Outcome::Failure((
+                    Status::PayloadTooLarge,
+                    Errors::TooLarge(limit.as_u64() as usize),
+                )),
+                Err(error) => Outcome::Failure((Status::BadRequest, error.into())),
+            };
+        }
+
+        if content_type.is_form_data() {
+            let file = match Form::<TempFile<'r>>::from_data(req, data).await {
+                Outcome::Success(file) => file.into_inner(),
+                Outcome::Failure((status, errors)) => {
+                    return Outcome::Failure((status, form_error(req, errors)))
+                }
+                Outcome::Forward(data) => return Outcome::Forward(data),
+            };
+            return match read_file(file).await {
+                Ok(bytes) => Outcome::Success(Self(ImageJson::Bytes(bytes))),
+                Err(error) => Outcome::Failure((Status::BadRequest, error)),
+            };
+        }
+
+        match Json::<ImageJson>::from_data(req, data).await {
+            Outcome::Success(image) => Outcome::Success(Self(image.into_inner())),
+            Outcome::Failure((status, error)) => Outcome::Failure((status, error.into())),
+            Outcome::Forward(data) => Outcome::Forward(data),
+        }
+    }
+}
+
+impl Deref for ImageBody {
+    type Target = ImageJson;
+
+    fn deref(&self) -> &ImageJson {
+        &self.0
+    }
+}
+
+/// A JSON body, or a `multipart/form-data` body with the JSON in its `json`
+/// field and files referenced from it as `{"upload": "<field name>"}`.
+pub struct WithUploads<T>(T);
+
+impl<T> WithUploads<T> {
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for WithUploads<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+#[rocket::async_trait]
+impl<'r, T: DeserializeOwned + ImageInputs + Send + 'static> FromData<'r> for WithUploads<T> {
+    type Error = Errors;
+
+    async fn from_data(req: &'r Request<'_>, data: Data<'r>) -> data::Outcome<'r, Self> {
+        if !req.content_type().is_some_and(|kind| kind.is_form_data()) {
+            return match Json::<T>::from_data(req, data).await {
+                Outcome::Success(body) => Outcome::Success(Self(body.into_inner())),
+                Outcome::Failure((status, error)) => Outcome::Failure((status, error.into())),
+                Outcome::Forward(data) => Outcome::Forward(data),
+            };
+        }
+
+        let form = match Form::<UploadForm<'r>>::from_data(req, data).await {
+            Outcome::Success(form) => form.into_inner(),
+            Outcome::Failure((status, errors)) => {
+                return Outcome::Failure((status, form_error(req, errors)))
+            }
+            Outcome::Forward(data) => return Outcome::Forward(data),
+        };
+        match form.into_body().await {
+            Ok(body) => Outcome::Success(Self(body)),
+            Err(error) => Outcome::Failure((Status::UnprocessableEntity, error)),
+        }
+    }
+}
+
+/// Multipart fields of a [`WithUploads`] body.
+struct UploadForm<'r> {
+    json: Option<&'r str>,
+    files: HashMap<String, TempFile<'r>>,
+}
+
+impl UploadForm<'_> {
+    /// Parses the JSON and swaps every upload reference for its file.
+    async fn into_body<T: DeserializeOwned + ImageInputs>(mut self) -> Result<T, Errors> {
+        let json = self
+            .json
+            .ok_or_else(|| Errors::InvalidInput("Missing the json field".into()))?;
+        let mut body: T = serde_json::from_str(json).map_err(Errors::JsonParse)?;
+        for image in body.images_mut() {
+            if let ImageJson::Upload(name) = image {
+                let file = self.files.remove(name.as_str()).ok_or_else(|| {
+                    Errors::InvalidInput(format!("No file uploaded as {:?}", name))
+                })?;
+                *image = ImageJson::Bytes(read_file(file).await?);
+            }
+        }
+        Ok(body)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromForm<'r> for UploadForm<'r> {
+    type Context = (UploadForm<'r>, form::Errors<'r>);
+
+    fn init(_opts: form::Options) -> Self::Context {
+        let form = UploadForm {
+            json: None,
+            files: HashMap::new(),
+        };
+        (form, form::Errors::new())
+    }
+
+    fn push_value((form, _): &mut Self::Context, field: ValueField<'r>) {
+        if field.name == "json" {
+            form.json = Some(field.value);
+        }
+    }
+
+    async fn push_data((form, errors): &mut Self::Context, field: DataField<'r, '_>) {
+        let name = field.name.source().to_string();
+        match <TempFile as form::FromFormField>::from_data(field).await {
+            Ok(file) => {
+                form.files.insert(name, file);
+            }
+            Err(error) => errors.extend(error),
+        }
+    }
+
+    fn finalize((form, errors): Self::Context) -> form::Result<'r, Self> {
+        if errors.is_empty() {
+            Ok(form)
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+async fn read_file(file: TempFile<'_>) -> Result<Vec<u8>, Errors> {
+    Ok(match file {
+        TempFile::Buffered { content } => content.as_bytes().to_vec(),
+        file => tokio::fs::read(file.path().unwrap()).await?,
+    })
+}
+
+fn form_error(req: &Request<'_>, errors: form::Errors<'_>) -> Errors {
+    // Cutting a file short can leave other errors behind
+    if errors
+        .iter()
+        .any(|error| error.status() == Status::PayloadTooLarge)
+    {
+        let limit = req.limits().get("file").unwrap_or(Limits::FILE);
+        return Errors::TooLarge(limit.as_u64() as usize);
+    }
+    Errors::InvalidInput(errors.to_string())
+}
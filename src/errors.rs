@@ -9,6 +9,19 @@ pub enum Errors {
     JsonParse(serde_json::error::Error),
     InvalidInput(String),
     InvalidTemplate(String),
+    /// The requested user or asset doesn't exist upstream
+    NotFound(String),
+    Timeout,
+    /// An upstream source didn't answer in time
+    UpstreamTimeout,
+    /// A download exceeded its size limit in bytes
+    TooLarge(usize),
+    /// An input image's dimensions or decoded size are over the limits
+    ImageTooLarge(String),
+    /// An upstream source is rate limiting us, with the seconds to wait if known
+    UpstreamRateLimited(Option<u64>),
+    /// An upstream source answered with an error status
+    UpstreamStatus(u16),
     InternalError(Box<dyn std::error::Error + Send>),
 }
 
@@ -24,6 +37,27 @@ impl Errors {
                     "message": format!("The requested image template {:?} is not found", name)
                 })
             }
+            Self::NotFound(message) => json!({"kind": "not_found", "message": message}),
+            Self::Timeout => {
+                json!({"kind": "timeout", "message": "Fetching the input images took too long"})
+            }
+            Self::UpstreamTimeout => {
+                json!({"kind": "upstream_timeout", "message": "The image source didn't respond in time"})
+            }
+            Self::TooLarge(limit) => json!({
+                "kind": "too_large",
+                "message": format!("The image is larger than {} bytes", limit)
+            }),
+            Self::ImageTooLarge(message) => json!({"kind": "image_too_large", "message": message}),
+            Self::UpstreamRateLimited(retry_after) => json!({
+                "kind": "upstream_rate_limited",
+                "message": "The image source is rate limiting requests, try again later",
+                "retry_after": retry_after
+            }),
+            Self::UpstreamStatus(status) => json!({
+                "kind": "upstream_status",
+                "message": format!("The image source responded with status {}", status)
+            }),
             Self::InternalError(_) => {
                 json!({"kind": "internal_error", "message": "An unknown internal error occurred."})
             }
@@ -34,7 +68,11 @@ impl Errors {
         match self {
             Self::JsonIo(..) => Status::BadRequest,
             Self::JsonParse(..) | Self::InvalidInput(..) => Status::UnprocessableEntity,
-            Self::InvalidTemplate(..) => Status::NotFound,
+            Self::InvalidTemplate(..) | Self::NotFound(..) => Status::NotFound,
+            Self::Timeout | Self::UpstreamTimeout => Status::GatewayTimeout,
+            Self::TooLarge(..) | Self::ImageTooLarge(..) => Status::PayloadTooLarge,
+            Self::UpstreamRateLimited(..) => Status::ServiceUnavailable,
+            Self::UpstreamStatus(..) => Status::BadGateway,
             Self::InternalError(..) => Status::InternalServerError,
         }
     }
@@ -42,7 +80,11 @@ impl Errors {
 
 impl<'r> rocket::response::Responder<'r, 'static> for Errors {
     fn respond_to(self, req: &'r rocket::Request<'_>) -> rocket::response::Result<'static> {
-        rocket::Response::build()
+        let mut response = rocket::Response::build();
+        if let Self::UpstreamRateLimited(Some(seconds)) = self {
+            response.raw_header("Retry-After", seconds.to_string());
+        }
+        response
             .status(self.status())
             .join(json!({"error": self.json()}).respond_to(req).unwrap())
             .ok()
@@ -60,6 +102,9 @@ impl From<JsonError<'_>> for Errors {
 
 impl From<reqwest::Error> for Errors {
     fn from(error: reqwest::Error) -> Self {
+        if error.is_timeout() {
+            return Self::UpstreamTimeout;
+        }
         Self::InvalidInput(error.to_string())
     }
 }
@@ -96,6 +141,13 @@ impl std::fmt::Display for Errors {
             Self::JsonParse(error) => error.fmt(fmt),
             Self::InvalidInput(error) => write!(fmt, "{}", error),
             Self::InvalidTemplate(name) => write!(fmt, "Invalid template name: {:?}", name),
+            Self::NotFound(message) => write!(fmt, "{}", message),
+            Self::Timeout => write!(fmt, "Timed out fetching input images"),
+            Self::UpstreamTimeout => write!(fmt, "Image source timed out"),
+            Self::TooLarge(limit) => write!(fmt, "Image larger than {} bytes", limit),
+            Self::ImageTooLarge(message) => write!(fmt, "{}", message),
+            Self::UpstreamRateLimited(..) => write!(fmt, "Image source rate limited"),
+            Self::UpstreamStatus(status) => write!(fmt, "Image source returned {}", status),
             Self::InternalError(error) => error.fmt(fmt),
         }
     }
@@ -0,0 +1,114 @@
+use image::{Rgba, RgbaImage};
+
+#[derive(Debug, Clone, Copy, FromFormField)]
+pub enum BlendSpace {
+    /// Straight mix of the sRGB channel values
+    Rgb,
+    /// Mix in linear light, then convert back to sRGB
+    Linear,
+    /// Shift hue and saturation towards the colour, keeping lightness
+    Hsl,
+}
+
+/// Blends `color` into every pixel by `amount` (`0.0..=1.0`), leaving alpha untouched.
+pub fn blend_color(image: &mut RgbaImage, color: [u8; 3], amount: f32, space: BlendSpace) {
+    let color_hsl = rgb_to_hsl(color);
+    for pixel in image.pixels_mut() {
+        let Rgba([r, g, b, a]) = *pixel;
+        let [r, g, b] = match space {
+            BlendSpace::Rgb => mix_rgb([r, g, b], color, amount, |c| c as f32, |c| c),
+            BlendSpace::Linear => mix_rgb([r, g, b], color, amount, srgb_to_linear, linear_to_srgb),
+            BlendSpace::Hsl => {
+                let (h, s, l) = rgb_to_hsl([r, g, b]);
+                let hue = mix_hue(h, color_hsl.0, amount);
+                let saturation = s + (color_hsl.1 - s) * amount;
+                hsl_to_rgb(hue, saturation, l)
+            }
+        };
+        *pixel = Rgba([r, g, b, a]);
+    }
+}
+
+fn mix_rgb(
+    pixel: [u8; 3],
+    color: [u8; 3],
+    amount: f32,
+    to_space: impl Fn(u8) -> f32,
+    from_space: impl Fn(f32) -> f32,
+) -> [u8; 3] {
+    let mut out = [0; 3];
+    for i in 0..3 {
+        let (p, c) = (to_space(pixel[i]), to_space(color[i]));
+        out[i] = from_space(p + (c - p) * amount).round().clamp(0.0, 255.0) as u8;
+    }
+    out
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+    let v = value as f32 / 255.0;
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    let v = if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    v * 255.0
+}
+
+/// Interpolates between two hues (degrees) along the shorter arc.
+fn mix_hue(from: f32, to: f32, amount: f32) -> f32 {
+    let mut delta = to - from;
+    if delta > 180.0 {
+        delta -= 360.0;
+    } else if delta < -180.0 {
+        delta += 360.0;
+    }
+    (from + delta * amount).rem_euclid(360.0)
+}
+
+fn rgb_to_hsl(rgb: [u8; 3]) -> (f32, f32, f32) {
+    let [r, g, b] = [
+        rgb[0] as f32 / 255.0,
+        rgb[1] as f32 / 255.0,
+        rgb[2] as f32 / 255.0,
+    ];
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let l = (max + min) / 2.0;
+    let delta = max - min;
+    if delta == 0.0 {
+        return (0.0, 0.0, l);
+    }
+
+    let s = delta / (1.0 - (2.0 * l - 1.0).abs());
+    let h = if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    (h, s, l)
+}
+
+fn hsl_to_rgb(h: f32, s: f32, l: f32) -> [u8; 3] {
+    let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    let x = c * (1.0 - ((h / 60.0).rem_euclid(2.0) - 1.0).abs());
+    let m = l - c / 2.0;
+    let (r, g, b) = match (h / 60.0) as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    [r, g, b].map(|v| ((v + m) * 255.0).round().clamp(0.0, 255.0) as u8)
+}
@@ -0,0 +1,105 @@
+use image::{imageops, DynamicImage, GenericImageView, Rgba, RgbaImage};
+
+use super::fit::Gravity;
+
+/// Position and size of one image on the collage canvas.
+#[derive(Debug, Clone, Copy)]
+pub struct Cell {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Lays `count` cells out row by row in a grid with `columns` columns.
+/// Returns the canvas size and the cells.
+pub fn grid(count: u32, columns: u32, cell: (u32, u32), gap: u32) -> ((u32, u32), Vec<Cell>) {
+    let rows = count.div_ceil(columns);
+    let canvas = (
+        columns * cell.0 + (columns + 1) * gap,
+        rows * cell.1 + (rows + 1) * gap,
+    );
+    let cells = (0..count)
+        .map(|index| Cell {
+            x: gap + (index % columns) * (cell.0 + gap),
+            y: gap + (index / columns) * (cell.1 + gap),
+            width: cell.0,
+            height: cell.1,
+        })
+        .collect();
+    (canvas, cells)
+}
+
+/// First image twice the cell size on the left, the rest stacked in a column on the right.
+/// A single image gets the whole canvas.
+pub fn feature(count: u32, cell: (u32, u32), gap: u32) -> ((u32, u32), Vec<Cell>) {
+    let feature_width = cell.0 * 2 + gap;
+    if count <= 1 {
+        return grid(count, 1, (feature_width, cell.1), gap);
+    }
+    let (column, side_cells) = grid(count - 1, 1, cell, gap);
+    let feature_height = column.1 - 2 * gap;
+
+    let mut cells = vec![Cell {
+        x: gap,
+        y: gap,
+        width: feature_width,
+        height: feature_height,
+    }];
+    cells.extend(
+        side_cells
+            .into_iter()
+            .take(count as usize - 1)
+            .map(|c| Cell {
+                x: c.x + feature_width + gap,
+                ..c
+            }),
+    );
+    ((column.0 + feature_width + gap, column.1), cells)
+}
+
+/// First image spanning the full width on top, the rest in a single row below.
+/// A single image gets the whole canvas.
+pub fn banner(count: u32, cell: (u32, u32), gap: u32) -> ((u32, u32), Vec<Cell>) {
+    let banner_height = cell.1 * 2 + gap;
+    if count <= 1 {
+        return grid(count, 1, (cell.0, banner_height), gap);
+    }
+    let (row, row_cells) = grid(count - 1, count - 1, cell, gap);
+
+    let mut cells = vec![Cell {
+        x: gap,
+        y: gap,
+        width: row.0 - 2 * gap,
+        height: banner_height,
+    }];
+    cells.extend(
+        row_cells
+            .into_iter()
+            .take(count as usize - 1)
+            .map(|c| Cell {
+                y: c.y + banner_height + gap,
+                ..c
+            }),
+    );
+    ((row.0, row.1 + banner_height + gap), cells)
+}
+
+/// Draws each image centred in its cell on a `background` canvas.
+pub fn compose(
+    canvas: (u32, u32),
+    background: Rgba<u8>,
+    images: &[(Cell, DynamicImage)],
+) -> RgbaImage {
+    let mut output = RgbaImage::from_pixel(canvas.0, canvas.1, background);
+    for (cell, image) in images {
+        let (x, y) = Gravity::Center.offset((cell.width, cell.height), image.dimensions());
+        imageops::overlay(
+            &mut output,
+            image,
+            cell.x + x.max(0) as u32,
+            cell.y + y.max(0) as u32,
+        );
+    }
+    output
+}
@@ -0,0 +1,102 @@
+use image::{GrayImage, Rgba, RgbaImage};
+
+pub struct DiffMetrics {
+    /// Pixels where any channel differs by more than the threshold
+    pub differing_pixels: u64,
+    pub rmse: f64,
+    /// `None` for identical images
+    pub psnr: Option<f64>,
+    pub ssim: f64,
+}
+
+/// Compares two images of equal dimensions.
+pub fn diff_metrics(a: &RgbaImage, b: &RgbaImage, threshold: u8) -> DiffMetrics {
+    let mut differing_pixels = 0;
+    let mut squared_error = 0f64;
+    for (pa, pb) in a.pixels().zip(b.pixels()) {
+        let mut differs = false;
+        for i in 0..4 {
+            let delta = pa[i] as i32 - pb[i] as i32;
+            squared_error += (delta * delta) as f64;
+            differs |= delta.unsigned_abs() > threshold as u32;
+        }
+        differing_pixels += differs as u64;
+    }
+
+    let samples = (a.width() as u64 * a.height() as u64 * 4).max(1) as f64;
+    let rmse = (squared_error / samples).sqrt();
+    let psnr = if rmse == 0.0 {
+        None
+    } else {
+        Some(20.0 * (255.0 / rmse).log10())
+    };
+    let ssim = ssim(
+        &image::DynamicImage::ImageRgba8(a.clone()).to_luma8(),
+        &image::DynamicImage::ImageRgba8(b.clone()).to_luma8(),
+    );
+
+    DiffMetrics {
+        differing_pixels,
+        rmse,
+        psnr,
+        ssim,
+    }
+}
+
+/// Mean structural similarity over 8×8 windows with a stride of 4.
+pub fn ssim(a: &GrayImage, b: &GrayImage) -> f64 {
+    const WINDOW: u32 = 8;
+    const STRIDE: usize = 4;
+    const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+    const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+    let (width, height) = a.dimensions();
+    let window_w = WINDOW.min(width);
+    let window_h = WINDOW.min(height);
+    let mut total = 0f64;
+    let mut windows = 0;
+
+    for y in (0..=height - window_h).step_by(STRIDE) {
+        for x in (0..=width - window_w).step_by(STRIDE) {
+            let count = (window_w * window_h) as f64;
+            let (mut sum_a, mut sum_b) = (0f64, 0f64);
+            let (mut sum_aa, mut sum_bb, mut sum_ab) = (0f64, 0f64, 0f64);
+            for wy in y..y + window_h {
+                for wx in x..x + window_w {
+                    let pa = a.get_pixel(wx, wy)[0] as f64;
+                    let pb = b.get_pixel(wx, wy)[0] as f64;
+                    sum_a += pa;
+                    sum_b += pb;
+                    sum_aa += pa * pa;
+                    sum_bb += pb * pb;
+                    sum_ab += pa * pb;
+                }
+            }
+            let (mean_a, mean_b) = (sum_a / count, sum_b / count);
+            let var_a = sum_aa / count - mean_a * mean_a;
+            let var_b = sum_bb / count - mean_b * mean_b;
+            let covariance = sum_ab / count - mean_a * mean_b;
+
+            total += ((2.0 * mean_a * mean_b + C1) * (2.0 * covariance + C2))
+                / ((mean_a * mean_a + mean_b * mean_b + C1) * (var_a + var_b + C2));
+            windows += 1;
+        }
+    }
+    total / windows.max(1) as f64
+}
+
+/// Dimmed grayscale copy of `a` with the pixels that differ from `b` in red.
+pub fn highlight_diff(a: &RgbaImage, b: &RgbaImage, threshold: u8) -> RgbaImage {
+    RgbaImage::from_fn(a.width(), a.height(), |x, y| {
+        let (pa, pb) = (a.get_pixel(x, y), b.get_pixel(x, y));
+        let differs =
+            (0..4).any(|i| (pa[i] as i32 - pb[i] as i32).unsigned_abs() > threshold as u32);
+        if differs {
+            Rgba([255, 0, 0, 255])
+        } else {
+            let gray = (0.299 * pa[0] as f32 + 0.587 * pa[1] as f32 + 0.114 * pa[2] as f32) as u8;
+            let dimmed = 128 + gray / 2;
+            Rgba([dimmed, dimmed, dimmed, 255])
+        }
+    })
+}
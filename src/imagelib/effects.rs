@@ -0,0 +1,242 @@
+use std::f32::consts::PI;
+
+use image::{
+    codecs::jpeg::JpegEncoder, imageops, imageops::FilterType, DynamicImage, ImageResult, Rgba,
+    RgbaImage,
+};
+use imageproc::{
+    geometric_transformations::{rotate_about_center, warp_with, Interpolation},
+    seam_carving::shrink_width,
+};
+
+use super::rng::Rng;
+
+/// Round trips the image through a low quality JPEG, keeping the original alpha.
+pub fn jpeg_crush(image: &RgbaImage, quality: u8) -> ImageResult<RgbaImage> {
+    let rgb = DynamicImage::ImageRgba8(image.clone()).to_rgb8();
+    let mut bytes = Vec::new();
+    JpegEncoder::new_with_quality(&mut bytes, quality).encode(
+        &rgb,
+        rgb.width(),
+        rgb.height(),
+        image::ColorType::Rgb8,
+    )?;
+
+    let mut crushed =
+        image::load_from_memory_with_format(&bytes, image::ImageFormat::Jpeg)?.to_rgba8();
+    for (pixel, original) in crushed.pixels_mut().zip(image.pixels()) {
+        pixel[3] = original[3];
+    }
+    Ok(crushed)
+}
+
+/// Oversaturates, sharpens contrast, adds noise and crushes the result.
+pub fn deepfry(image: &RgbaImage, seed: u64) -> ImageResult<RgbaImage> {
+    let mut rng = Rng::new(seed);
+    let mut fried = image.clone();
+
+    for pixel in fried.pixels_mut() {
+        let [r, g, b] = [pixel[0] as f32, pixel[1] as f32, pixel[2] as f32];
+        let gray = 0.299 * r + 0.587 * g + 0.114 * b;
+        for (i, value) in [r, g, b].iter().enumerate() {
+            let saturated = gray + (value - gray) * 3.0;
+            let contrasted = (saturated - 128.0) * 1.5 + 128.0;
+            let noise = rng.range(-24, 25) as f32;
+            pixel[i] = (contrasted + noise).clamp(0.0, 255.0) as u8;
+        }
+    }
+    jpeg_crush(&fried, 8)
+}
+
+/// Splits the colour channels sideways and shifts random horizontal slices.
+pub fn glitch(image: &RgbaImage, seed: u64, amount: u32) -> RgbaImage {
+    let mut rng = Rng::new(seed);
+    let (width, height) = image.dimensions();
+    let shift = amount as i64;
+    let sample = |x: i64, y: u32, channel: usize| {
+        image.get_pixel(x.clamp(0, width as i64 - 1) as u32, y)[channel]
+    };
+
+    let mut output = RgbaImage::new(width, height);
+    let mut y = 0;
+    while y < height {
+        let slice_height = rng.range(1, (height as i64 / 8).max(2)) as u32;
+        let offset = if rng.range(0, 3) == 0 {
+            rng.range(-shift * 2, shift * 2 + 1)
+        } else {
+            0
+        };
+        for row in y..(y + slice_height).min(height) {
+            for x in 0..width {
+                let source = x as i64 + offset;
+                output.put_pixel(
+                    x,
+                    row,
+                    Rgba([
+                        sample(source + shift, row, 0),
+                        sample(source, row, 1),
+                        sample(source - shift, row, 2),
+                        sample(source, row, 3),
+                    ]),
+                );
+            }
+        }
+        y += slice_height;
+    }
+    output
+}
+
+/// Twists the image around its centre, strongest in the middle.
+pub fn swirl(image: &RgbaImage, degrees: f32) -> RgbaImage {
+    let (cx, cy) = (image.width() as f32 / 2.0, image.height() as f32 / 2.0);
+    let radius = cx.min(cy);
+    let strength = degrees.to_radians();
+
+    warp_with(
+        image,
+        |x, y| {
+            let (dx, dy) = (x - cx, y - cy);
+            let distance = (dx * dx + dy * dy).sqrt();
+            if distance >= radius {
+                return (x, y);
+            }
+            let falloff = 1.0 - distance / radius;
+            let angle = dy.atan2(dx) + strength * falloff * falloff;
+            (cx + distance * angle.cos(), cy + distance * angle.sin())
+        },
+        Interpolation::Bilinear,
+        Rgba([0; 4]),
+    )
+}
+
+/// Magnifies (positive `strength`) or pinches (negative) the centre.
+/// `strength` is in `-1.0..=1.0`.
+pub fn bulge(image: &RgbaImage, strength: f32) -> RgbaImage {
+    let (cx, cy) = (image.width() as f32 / 2.0, image.height() as f32 / 2.0);
+    let radius = cx.min(cy);
+    let exponent = 2f32.powf(strength);
+
+    warp_with(
+        image,
+        |x, y| {
+            let (dx, dy) = (x - cx, y - cy);
+            let distance = (dx * dx + dy * dy).sqrt();
+            if distance >= radius || distance == 0.0 {
+                return (x, y);
+            }
+            let scale = (distance / radius).powf(exponent) * radius / distance;
+            (cx + dx * scale, cy + dy * scale)
+        },
+        Interpolation::Bilinear,
+        Rgba([0; 4]),
+    )
+}
+
+/// Moves every pixel to a random spot within `radius`.
+pub fn spread(image: &RgbaImage, seed: u64, radius: u32) -> RgbaImage {
+    let mut rng = Rng::new(seed);
+    let (width, height) = image.dimensions();
+    let radius = radius as i64;
+
+    RgbaImage::from_fn(width, height, |x, y| {
+        let sx = (x as i64 + rng.range(-radius, radius + 1)).clamp(0, width as i64 - 1);
+        let sy = (y as i64 + rng.range(-radius, radius + 1)).clamp(0, height as i64 - 1);
+        *image.get_pixel(sx as u32, sy as u32)
+    })
+}
+
+/// Liquid rescale: seam carves both axes down to `scale` and stretches the
+/// result back to the original size.
+pub fn magik(image: &RgbaImage, scale: f32) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let target = |size: u32| ((size as f32 * scale) as u32).max(1);
+
+    let carved = shrink_width(image, target(width));
+    let carved = imageops::rotate90(&carved);
+    let carved = shrink_width(&carved, target(height));
+    let carved = imageops::rotate270(&carved);
+    imageops::resize(&carved, width, height, FilterType::Triangle)
+}
+
+/// Frames of the image jittering around by up to `intensity` pixels.
+pub fn shake_frames(image: &RgbaImage, seed: u64, intensity: u32, frames: u32) -> Vec<RgbaImage> {
+    let mut rng = Rng::new(seed);
+    let intensity = intensity as i64;
+    let (width, height) = image.dimensions();
+
+    (0..frames)
+        .map(|_| {
+            let dx = rng.range(-intensity, intensity + 1);
+            let dy = rng.range(-intensity, intensity + 1);
+            RgbaImage::from_fn(width, height, |x, y| {
+                let (sx, sy) = (x as i64 - dx, y as i64 - dy);
+                if sx < 0 || sy < 0 || sx >= width as i64 || sy >= height as i64 {
+                    return Rgba([0; 4]);
+                }
+                *image.get_pixel(sx as u32, sy as u32)
+            })
+        })
+        .collect()
+}
+
+/// Frames of one full clockwise turn.
+pub fn spin_frames(image: &RgbaImage, frames: u32) -> Vec<RgbaImage> {
+    (0..frames)
+        .map(|index| {
+            let theta = 2.0 * PI * index as f32 / frames as f32;
+            rotate_about_center(image, theta, Interpolation::Bilinear, Rgba([0; 4]))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input() -> RgbaImage {
+        RgbaImage::from_fn(32, 32, |x, y| {
+            Rgba([(x * 8) as u8, (y * 8) as u8, ((x + y) * 4) as u8, 255])
+        })
+    }
+
+    /// FNV-1a of the pixels, enough to tell outputs apart.
+    fn hash(image: &RgbaImage) -> u64 {
+        image
+            .as_raw()
+            .iter()
+            .fold(0xcbf29ce484222325, |hash, &byte| {
+                (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+            })
+    }
+
+    #[test]
+    fn deepfry_is_pinned_by_seed() {
+        let image = deepfry(&input(), 1).unwrap();
+        assert_eq!(hash(&image), 0x72ac266ffda49c79);
+        assert_eq!(hash(&deepfry(&input(), 1).unwrap()), hash(&image));
+        assert_ne!(hash(&deepfry(&input(), 2).unwrap()), hash(&image));
+    }
+
+    #[test]
+    fn glitch_is_pinned_by_seed() {
+        assert_eq!(hash(&glitch(&input(), 1, 4)), 0xf33e2d266e821c25);
+        assert_ne!(hash(&glitch(&input(), 2, 4)), hash(&glitch(&input(), 1, 4)));
+    }
+
+    #[test]
+    fn spread_is_pinned_by_seed() {
+        assert_eq!(hash(&spread(&input(), 1, 3)), 0x007b59689878e741);
+        assert_ne!(hash(&spread(&input(), 2, 3)), hash(&spread(&input(), 1, 3)));
+    }
+
+    #[test]
+    fn shake_frames_are_pinned_by_seed() {
+        let frames = shake_frames(&input(), 1, 4, 3);
+        assert_eq!(frames.len(), 3);
+        let hashes: Vec<u64> = frames.iter().map(hash).collect();
+        assert_eq!(
+            hashes,
+            [0xdb3859fd3a19d99c, 0x9af2c8f8dc5b9329, 0x60e47736c1c5117c]
+        );
+    }
+}
@@ -1,5 +1,7 @@
 use image::{Rgb, RgbImage};
 
+use crate::errors::Errors;
+
 pub fn fill_color(color: [u8; 3], size: (u32, u32)) -> RgbImage {
     let mut img = RgbImage::new(size.0, size.1);
 
@@ -10,3 +12,18 @@ pub fn fill_color(color: [u8; 3], size: (u32, u32)) -> RgbImage {
     }
     img
 }
+
+/// Parses `rrggbb` or `rrggbbaa`, with or without a leading `#`.
+pub fn parse_hex_color(text: &str) -> Result<[u8; 4], Errors> {
+    let hex = text.trim_start_matches('#');
+    let invalid = || Errors::InvalidInput(format!("Invalid hex color: {:?}", text));
+    if !(hex.len() == 6 || hex.len() == 8) || !hex.is_ascii() {
+        return Err(invalid());
+    }
+
+    let mut color = [255; 4];
+    for (i, channel) in color.iter_mut().enumerate().take(hex.len() / 2) {
+        *channel = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+    }
+    Ok(color)
+}
@@ -0,0 +1,125 @@
+use image::{DynamicImage, GrayImage, Luma, Rgb, Rgba, RgbaImage};
+use imageproc::{
+    filter::{filter3x3, Kernel},
+    gradients::sobel_gradients,
+};
+
+pub fn sepia(image: &mut RgbaImage) {
+    for pixel in image.pixels_mut() {
+        let [r, g, b] = [pixel[0] as f32, pixel[1] as f32, pixel[2] as f32];
+        pixel[0] = (0.393 * r + 0.769 * g + 0.189 * b).min(255.0) as u8;
+        pixel[1] = (0.349 * r + 0.686 * g + 0.168 * b).min(255.0) as u8;
+        pixel[2] = (0.272 * r + 0.534 * g + 0.131 * b).min(255.0) as u8;
+    }
+}
+
+/// Reduces every colour channel to `levels` evenly spaced values.
+pub fn posterize(image: &mut RgbaImage, levels: u8) {
+    let step = 255.0 / (levels - 1) as f32;
+    for pixel in image.pixels_mut() {
+        for channel in pixel.0.iter_mut().take(3) {
+            *channel = ((*channel as f32 / step).round() * step) as u8;
+        }
+    }
+}
+
+/// Replaces each `block × block` square with its average colour.
+pub fn pixelate(image: &RgbaImage, block: u32) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let mut output = RgbaImage::new(width, height);
+
+    for block_y in (0..height).step_by(block as usize) {
+        for block_x in (0..width).step_by(block as usize) {
+            let x_end = (block_x + block).min(width);
+            let y_end = (block_y + block).min(height);
+
+            let mut sum = [0u64; 4];
+            for y in block_y..y_end {
+                for x in block_x..x_end {
+                    for (total, channel) in sum.iter_mut().zip(image.get_pixel(x, y).0.iter()) {
+                        *total += *channel as u64;
+                    }
+                }
+            }
+            let count = ((x_end - block_x) * (y_end - block_y)) as u64;
+            let average = Rgba(sum.map(|total| (total / count) as u8));
+
+            for y in block_y..y_end {
+                for x in block_x..x_end {
+                    output.put_pixel(x, y, average);
+                }
+            }
+        }
+    }
+    output
+}
+
+/// Sobel gradient magnitude, clamped to the `u8` range.
+pub fn sobel_edges(image: &GrayImage) -> GrayImage {
+    let gradients = sobel_gradients(image);
+    GrayImage::from_fn(image.width(), image.height(), |x, y| {
+        Luma([gradients.get_pixel(x, y)[0].min(255) as u8])
+    })
+}
+
+pub fn emboss(image: &RgbaImage) -> RgbaImage {
+    let kernel: [f32; 9] = [-2.0, -1.0, 0.0, -1.0, 1.0, 1.0, 0.0, 1.0, 2.0];
+    filter3x3(image, &kernel)
+}
+
+/// Correlates the colour channels with a square, row-major `kernel`, then
+/// applies `output = sum / divisor + bias`. Alpha is left untouched.
+pub fn convolve(
+    image: &RgbaImage,
+    kernel: &[f32],
+    size: u32,
+    divisor: f32,
+    bias: f32,
+) -> RgbaImage {
+    let rgb = DynamicImage::ImageRgba8(image.clone()).to_rgb8();
+    let filtered = Kernel::new(kernel, size, size).filter::<_, _, Rgb<u8>>(&rgb, |channel, sum| {
+        *channel = (sum / divisor + bias).round().clamp(0.0, 255.0) as u8;
+    });
+
+    RgbaImage::from_fn(image.width(), image.height(), |x, y| {
+        let Rgb([r, g, b]) = *filtered.get_pixel(x, y);
+        Rgba([r, g, b, image.get_pixel(x, y)[3]])
+    })
+}
+
+/// Edge preserving blur, neighbours are weighted by both distance and
+/// colour difference.
+pub fn bilateral(image: &RgbaImage, radius: u32, sigma_color: f32, sigma_space: f32) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let radius = radius as i64;
+    let color_factor = -0.5 / (sigma_color * sigma_color);
+    let space_factor = -0.5 / (sigma_space * sigma_space);
+
+    RgbaImage::from_fn(width, height, |x, y| {
+        let center = image.get_pixel(x, y);
+        let mut sum = [0f32; 4];
+        let mut weight_sum = 0f32;
+
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                let (nx, ny) = (x as i64 + dx, y as i64 + dy);
+                if nx < 0 || ny < 0 || nx >= width as i64 || ny >= height as i64 {
+                    continue;
+                }
+                let neighbour = image.get_pixel(nx as u32, ny as u32);
+                let color_distance: f32 = (0..3)
+                    .map(|i| (neighbour[i] as f32 - center[i] as f32).powi(2))
+                    .sum();
+                let weight = ((dx * dx + dy * dy) as f32 * space_factor
+                    + color_distance * color_factor)
+                    .exp();
+
+                for (total, channel) in sum.iter_mut().zip(neighbour.0.iter()) {
+                    *total += *channel as f32 * weight;
+                }
+                weight_sum += weight;
+            }
+        }
+        Rgba(sum.map(|total| (total / weight_sum).round() as u8))
+    })
+}
@@ -0,0 +1,104 @@
+use image::{imageops::FilterType, DynamicImage, GenericImageView, Rgba, RgbaImage};
+use serde::Deserialize;
+
+/// How an image is scaled into a target box.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, FromFormField)]
+#[serde(rename_all = "lowercase")]
+pub enum Fit {
+    /// Preserve aspect ratio and pad to the exact box
+    Contain,
+    /// Preserve aspect ratio and crop the overflow to the exact box
+    Cover,
+    /// Stretch to the exact box, ignoring aspect ratio
+    Fill,
+    /// Preserve aspect ratio, result fits within the box
+    Inside,
+}
+
+/// Where an image is anchored when cropping or padding.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, FromFormField)]
+#[serde(rename_all = "lowercase")]
+pub enum Gravity {
+    Center,
+    North,
+    NorthEast,
+    East,
+    SouthEast,
+    South,
+    SouthWest,
+    West,
+    NorthWest,
+}
+
+impl Gravity {
+    /// Offset of an `inner` sized box placed inside `outer` at this anchor.
+    /// Negative when `inner` is larger than `outer` on that axis.
+    pub fn offset(self, outer: (u32, u32), inner: (u32, u32)) -> (i64, i64) {
+        let (free_x, free_y) = (
+            outer.0 as i64 - inner.0 as i64,
+            outer.1 as i64 - inner.1 as i64,
+        );
+        let x = match self {
+            Self::NorthWest | Self::West | Self::SouthWest => 0,
+            Self::North | Self::Center | Self::South => free_x / 2,
+            Self::NorthEast | Self::East | Self::SouthEast => free_x,
+        };
+        let y = match self {
+            Self::NorthWest | Self::North | Self::NorthEast => 0,
+            Self::West | Self::Center | Self::East => free_y / 2,
+            Self::SouthWest | Self::South | Self::SouthEast => free_y,
+        };
+        (x, y)
+    }
+}
+
+pub fn fit(
+    image: &DynamicImage,
+    size: (u32, u32),
+    fit: Fit,
+    gravity: Gravity,
+    background: Rgba<u8>,
+    filter: FilterType,
+) -> DynamicImage {
+    match fit {
+        Fit::Fill => image.resize_exact(size.0, size.1, filter),
+        Fit::Inside => image.resize(size.0, size.1, filter),
+        Fit::Contain => pad(
+            &image.resize(size.0, size.1, filter),
+            size,
+            gravity,
+            background,
+        ),
+        Fit::Cover => {
+            let (width, height) = image.dimensions();
+            let scale = f64::max(size.0 as f64 / width as f64, size.1 as f64 / height as f64);
+            let scaled = image.resize_exact(
+                ((width as f64 * scale).round() as u32).max(size.0),
+                ((height as f64 * scale).round() as u32).max(size.1),
+                filter,
+            );
+            crop(&scaled, size, gravity)
+        }
+    }
+}
+
+/// Cuts a `size` region out of `image` at `gravity`, clamped to the image bounds.
+pub fn crop(image: &DynamicImage, size: (u32, u32), gravity: Gravity) -> DynamicImage {
+    let (x, y) = gravity.offset(image.dimensions(), size);
+    image.crop_imm(x.max(0) as u32, y.max(0) as u32, size.0, size.1)
+}
+
+/// Places `image` on a `size` canvas filled with `background` at `gravity`.
+/// Parts of the image that don't fit on the canvas are cut off.
+pub fn pad(
+    image: &DynamicImage,
+    size: (u32, u32),
+    gravity: Gravity,
+    background: Rgba<u8>,
+) -> DynamicImage {
+    let (x, y) = gravity.offset(size, image.dimensions());
+    let visible = image.crop_imm((-x).max(0) as u32, (-y).max(0) as u32, size.0, size.1);
+    let mut canvas = RgbaImage::from_pixel(size.0, size.1, background);
+    image::imageops::overlay(&mut canvas, &visible, x.max(0) as u32, y.max(0) as u32);
+    DynamicImage::ImageRgba8(canvas)
+}
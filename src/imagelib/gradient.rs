@@ -0,0 +1,52 @@
+use image::{Rgba, RgbaImage};
+
+/// Colour at `t` along a list of `(offset, color)` stops sorted by offset.
+pub fn sample_stops(stops: &[(f32, [u8; 4])], t: f32) -> Rgba<u8> {
+    let first = stops[0];
+    let last = stops[stops.len() - 1];
+    if t <= first.0 {
+        return Rgba(first.1);
+    }
+    if t >= last.0 {
+        return Rgba(last.1);
+    }
+
+    let index = stops.iter().position(|(offset, _)| *offset > t).unwrap();
+    let (start, from) = stops[index - 1];
+    let (end, to) = stops[index];
+    let weight = if end > start {
+        (t - start) / (end - start)
+    } else {
+        1.0
+    };
+
+    let mut color = [0; 4];
+    for i in 0..4 {
+        color[i] = (from[i] as f32 + (to[i] as f32 - from[i] as f32) * weight).round() as u8;
+    }
+    Rgba(color)
+}
+
+/// Linear gradient along `angle` degrees, measured clockwise from left→right.
+pub fn linear_gradient(stops: &[(f32, [u8; 4])], angle: f32, size: (u32, u32)) -> RgbaImage {
+    let (dx, dy) = (angle.to_radians().cos(), angle.to_radians().sin());
+    let (cx, cy) = (size.0 as f32 / 2.0, size.1 as f32 / 2.0);
+    // Length of the gradient line, chosen so the corners hit offsets 0 and 1
+    let length = (size.0 as f32 * dx).abs() + (size.1 as f32 * dy).abs();
+
+    RgbaImage::from_fn(size.0, size.1, |x, y| {
+        let projected = (x as f32 + 0.5 - cx) * dx + (y as f32 + 0.5 - cy) * dy;
+        sample_stops(stops, projected / length + 0.5)
+    })
+}
+
+/// Radial gradient from the centre (offset 0) to the corners (offset 1).
+pub fn radial_gradient(stops: &[(f32, [u8; 4])], size: (u32, u32)) -> RgbaImage {
+    let (cx, cy) = (size.0 as f32 / 2.0, size.1 as f32 / 2.0);
+    let radius = (cx * cx + cy * cy).sqrt();
+
+    RgbaImage::from_fn(size.0, size.1, |x, y| {
+        let (px, py) = (x as f32 + 0.5 - cx, y as f32 + 0.5 - cy);
+        sample_stops(stops, (px * px + py * py).sqrt() / radius)
+    })
+}
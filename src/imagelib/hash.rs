@@ -0,0 +1,69 @@
+use std::f32::consts::PI;
+
+use image::{imageops::FilterType, DynamicImage};
+
+/// Average hash: 8×8 grayscale, one bit per pixel brighter than the mean.
+pub fn ahash(image: &DynamicImage) -> u64 {
+    let gray = image.resize_exact(8, 8, FilterType::Triangle).to_luma8();
+    let mean = gray.pixels().map(|p| p[0] as u32).sum::<u32>() / 64;
+    bits(gray.pixels().map(|p| p[0] as u32 > mean))
+}
+
+/// Difference hash: 9×8 grayscale, one bit per pixel brighter than its right neighbour.
+pub fn dhash(image: &DynamicImage) -> u64 {
+    let gray = image.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+    bits((0..8).flat_map(|y| {
+        let gray = &gray;
+        (0..8).map(move |x| gray.get_pixel(x, y)[0] > gray.get_pixel(x + 1, y)[0])
+    }))
+}
+
+/// DCT based perceptual hash: the low frequency 8×8 block of a 32×32
+/// grayscale DCT, one bit per coefficient above the median.
+pub fn phash(image: &DynamicImage) -> u64 {
+    const SIZE: usize = 32;
+    let gray = image
+        .resize_exact(SIZE as u32, SIZE as u32, FilterType::Triangle)
+        .to_luma8();
+    let pixels: Vec<f32> = gray.pixels().map(|p| p[0] as f32).collect();
+
+    // Separable 2D DCT-II, only the 8 lowest frequencies on each axis are needed
+    let cosines: Vec<f32> = (0..8 * SIZE)
+        .map(|i| {
+            let (u, x) = (i / SIZE, i % SIZE);
+            ((2 * x + 1) as f32 * u as f32 * PI / (2 * SIZE) as f32).cos()
+        })
+        .collect();
+    let mut rows = vec![0f32; SIZE * 8];
+    for y in 0..SIZE {
+        for u in 0..8 {
+            rows[y * 8 + u] = (0..SIZE)
+                .map(|x| pixels[y * SIZE + x] * cosines[u * SIZE + x])
+                .sum();
+        }
+    }
+    let mut coefficients = [0f32; 64];
+    for v in 0..8 {
+        for u in 0..8 {
+            coefficients[v * 8 + u] = (0..SIZE)
+                .map(|y| rows[y * 8 + u] * cosines[v * SIZE + y])
+                .sum();
+        }
+    }
+
+    // The DC term skews the median, leave it out
+    let mut sorted = coefficients[1..].to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let median = sorted[sorted.len() / 2];
+    bits(coefficients.iter().map(|c| *c > median))
+}
+
+fn bits(values: impl Iterator<Item = bool>) -> u64 {
+    values.fold(0, |hash, bit| (hash << 1) | bit as u64)
+}
+
+/// Number of differing bits between two hashes.
+#[inline]
+pub fn distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
@@ -1,13 +1,52 @@
-use std::io::Cursor;
+use std::{io::Cursor, sync::Arc};
 
-use image::DynamicImage;
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    Delay, DynamicImage, Frame, RgbaImage,
+};
 use rocket::{
     http::{ContentType, Status},
     response::{Responder, Response},
     Request,
 };
 
-use crate::{errors::Errors, state::serverstate::ServerState};
+use crate::{
+    errors::Errors,
+    state::{render_cache::hash_bytes, serverstate::ServerState},
+};
+
+pub fn encode_png(image: &DynamicImage) -> image::ImageResult<Vec<u8>> {
+    let mut bytes: Vec<u8> = Vec::new();
+    image.write_to(&mut bytes, image::ImageOutputFormat::Png)?;
+    Ok(bytes)
+}
+
+/// Sends `bytes` tagged with `etag`, or an empty 304 when the client's
+/// `If-None-Match` already lists it.
+fn respond_tagged(
+    request: &Request<'_>,
+    content_type: ContentType,
+    bytes: Vec<u8>,
+    etag: &str,
+) -> Result<Response<'static>, Status> {
+    let etag = format!("\"{}\"", etag);
+    let not_modified = request
+        .headers()
+        .get("If-None-Match")
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == etag || tag == "*");
+
+    let mut response = Response::build();
+    response.raw_header("ETag", etag);
+    if not_modified {
+        return response.status(Status::NotModified).ok();
+    }
+    response
+        .header(content_type)
+        .sized_body(bytes.len(), Cursor::new(bytes))
+        .ok()
+}
 
 pub struct ImageResponse(pub DynamicImage);
 
@@ -22,13 +61,60 @@ impl<'r> Responder<'r, 'static> for ImageResponse {
     fn respond_to(self, request: &'r Request<'_>) -> Result<Response<'static>, Status> {
         let _state = request.rocket().state::<&ServerState>().unwrap();
 
-        let mut bytes: Vec<u8> = Vec::new();
-        if let Err(err) = self.0.write_to(&mut bytes, image::ImageOutputFormat::Png) {
-            return Errors::from(err).respond_to(request);
+        let bytes = match encode_png(&self.0) {
+            Ok(bytes) => bytes,
+            Err(err) => return Errors::from(err).respond_to(request),
         };
-        Response::build()
-            .header(ContentType::PNG)
-            .sized_body(bytes.len(), Cursor::new(bytes))
-            .ok()
+        let etag = hash_bytes(&bytes);
+        respond_tagged(request, ContentType::PNG, bytes, &etag)
+    }
+}
+
+/// PNG from the render cache, tagged with its render key.
+pub struct CachedImageResponse {
+    pub etag: String,
+    pub bytes: Arc<Vec<u8>>,
+}
+
+impl<'r> Responder<'r, 'static> for CachedImageResponse {
+    fn respond_to(self, request: &'r Request<'_>) -> Result<Response<'static>, Status> {
+        respond_tagged(request, ContentType::PNG, self.bytes.to_vec(), &self.etag)
+    }
+}
+
+/// NeuQuant sampling factor, 1 is the slowest and most accurate
+const GIF_SPEED: i32 = 10;
+
+/// Encodes a looping GIF, every frame shown for the same delay. Quantizing
+/// is slow, call it from a blocking task.
+pub fn encode_gif(frames: Vec<RgbaImage>, delay_ms: u32) -> image::ImageResult<Vec<u8>> {
+    let mut bytes: Vec<u8> = Vec::new();
+    {
+        let mut encoder = GifEncoder::new_with_speed(&mut bytes, GIF_SPEED);
+        encoder.set_repeat(Repeat::Infinite)?;
+        let delay = Delay::from_numer_denom_ms(delay_ms, 1);
+        encoder.encode_frames(
+            frames
+                .into_iter()
+                .map(|frame| Frame::from_parts(frame, 0, 0, delay)),
+        )?;
+    }
+    Ok(bytes)
+}
+
+/// GIF encoded by [`encode_gif`].
+pub struct AnimationResponse(pub Vec<u8>);
+
+impl AnimationResponse {
+    #[inline]
+    pub fn ok<T>(self) -> Result<Self, T> {
+        Ok(self)
+    }
+}
+
+impl<'r> Responder<'r, 'static> for AnimationResponse {
+    fn respond_to(self, request: &'r Request<'_>) -> Result<Response<'static>, Status> {
+        let etag = hash_bytes(&self.0);
+        respond_tagged(request, ContentType::GIF, self.0, &etag)
     }
 }
@@ -0,0 +1,155 @@
+use std::io::Cursor;
+
+use gif::{ColorOutput, DecodeOptions, DecodingError};
+use image::{io::Reader, ColorType, GenericImageView, ImageFormat, ImageResult};
+
+use crate::{errors::Errors, state::config::ServerConfig};
+
+pub struct ImageInfo {
+    pub format: ImageFormat,
+    pub dimensions: (u32, u32),
+    pub color_type: ColorType,
+    /// Delay of every frame in milliseconds, empty for still images
+    pub frame_durations: Vec<u32>,
+    /// Whether any pixel is not fully opaque
+    pub transparent: bool,
+}
+
+/// Reads the dimensions from the image header without decoding the pixels.
+pub fn header_dimensions(bytes: &[u8]) -> ImageResult<(u32, u32)> {
+    Reader::new(Cursor::new(bytes))
+        .with_guessed_format()?
+        .into_dimensions()
+}
+
+/// Reads format, size and colour information from encoded image bytes.
+/// GIF frames are scanned one at a time as palette indices, within the
+/// frame limit and memory budget of `config`.
+pub fn image_info(bytes: &[u8], config: &ServerConfig) -> Result<ImageInfo, Errors> {
+    let format = image::guess_format(bytes)?;
+    if format == ImageFormat::Gif {
+        return gif_info(bytes, config);
+    }
+
+    let (width, height) = header_dimensions(bytes)?;
+    config.check_decode_size((width, height))?;
+    config.check_memory_budget(width as u64 * height as u64 * 4)?;
+    let image = Reader::with_format(Cursor::new(bytes), format).decode()?;
+    let color_type = image.color();
+    let transparent = color_type.has_alpha() && image.pixels().any(|(_, _, p)| p[3] < 255);
+    Ok(ImageInfo {
+        format,
+        dimensions: image.dimensions(),
+        color_type,
+        frame_durations: vec![],
+        transparent,
+    })
+}
+
+fn gif_info(bytes: &[u8], config: &ServerConfig) -> Result<ImageInfo, Errors> {
+    let invalid = |error: DecodingError| Errors::InvalidInput(error.to_string());
+    let mut options = DecodeOptions::new();
+    options.set_color_output(ColorOutput::Indexed);
+    let mut decoder = options.read_info(bytes).map_err(invalid)?;
+    let dimensions = (decoder.width() as u32, decoder.height() as u32);
+    config.check_decode_size(dimensions)?;
+
+    let mut frame_durations = vec![];
+    let mut transparent = false;
+    let mut buffer = vec![];
+    while let Some(frame) = decoder.next_frame_info().map_err(invalid)? {
+        // Later frames are skipped, so only their count is limited
+        if frame_durations.len() >= config.decode_max_frames {
+            return Err(Errors::ImageTooLarge(format!(
+                "Animations can have at most {} frames",
+                config.decode_max_frames
+            )));
+        }
+        config.check_decode_size((frame.width as u32, frame.height as u32))?;
+
+        frame_durations.push(frame.delay as u32 * 10);
+        // Only the first frame shows the canvas itself, later ones draw over it
+        if frame_durations.len() == 1 {
+            let covers = (frame.left, frame.top) == (0, 0)
+                && (frame.width as u32, frame.height as u32) == dimensions;
+            let transparent_index = frame.transparent;
+            // The one buffer decoded into, sized for the frame's indices
+            config.check_memory_budget(decoder.buffer_size() as u64)?;
+            buffer.resize(decoder.buffer_size(), 0);
+            decoder.read_into_buffer(&mut buffer).map_err(invalid)?;
+            transparent =
+                !covers || matches!(transparent_index, Some(index) if buffer.contains(&index));
+        }
+    }
+
+    Ok(ImageInfo {
+        format: ImageFormat::Gif,
+        dimensions,
+        color_type: ColorType::Rgba8,
+        frame_durations,
+        transparent,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use figment::{
+        providers::{Format, Toml},
+        Figment,
+    };
+    use gif::{Encoder, Frame};
+
+    use super::*;
+
+    fn config(extra: &str) -> ServerConfig {
+        let toml = format!(
+            r#"
+            templates = []
+            default_font = "font.ttf"
+            textdraw_text_max_len = 100
+            blur_sigma = 2.0
+            colorfill_image_size = 256
+            allow_local_file_input = false
+            resize_filtertype = "triangle"
+            {}
+            "#,
+            extra
+        );
+        Figment::from(Toml::string(&toml)).extract().unwrap()
+    }
+
+    /// A large canvas with small frames, cheap to encode but not to decode
+    /// every frame of.
+    fn animation(frames: usize) -> Vec<u8> {
+        let mut bytes = vec![];
+        let mut encoder = Encoder::new(&mut bytes, 2000, 2000, &[0, 0, 0, 255, 255, 255]).unwrap();
+        for _ in 0..frames {
+            let frame = Frame {
+                width: 10,
+                height: 10,
+                delay: 5,
+                buffer: Cow::Owned(vec![1; 100]),
+                ..Frame::default()
+            };
+            encoder.write_frame(&frame).unwrap();
+        }
+        drop(encoder);
+        bytes
+    }
+
+    #[test]
+    fn large_animation_fits_budget() {
+        let info = image_info(&animation(40), &config("")).unwrap();
+        assert_eq!(info.dimensions, (2000, 2000));
+        assert_eq!(info.frame_durations, vec![50; 40]);
+        assert!(info.transparent);
+    }
+
+    #[test]
+    fn frame_count_is_limited() {
+        let result = image_info(&animation(4), &config("decode_max_frames = 3"));
+        assert!(matches!(result, Err(Errors::ImageTooLarge(_))));
+    }
+}
@@ -1,3 +1,16 @@
+pub mod blend;
+pub mod collage;
+pub mod compare;
 pub mod drawtext;
+pub mod effects;
 pub mod fillcolor;
+pub mod filters;
+pub mod fit;
+pub mod gradient;
+pub mod hash;
 pub mod image_response;
+pub mod info;
+pub mod palette;
+pub mod pattern;
+pub mod rng;
+pub mod rotate;
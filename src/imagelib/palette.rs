@@ -0,0 +1,107 @@
+use image::{Rgb, RgbImage, RgbaImage};
+
+/// Pixels with alpha at or below this are ignored when picking colours.
+const ALPHA_CUTOFF: u8 = 16;
+
+fn opaque_pixels(image: &RgbaImage) -> Vec<[u8; 3]> {
+    image
+        .pixels()
+        .filter(|p| p[3] > ALPHA_CUTOFF)
+        .map(|p| [p[0], p[1], p[2]])
+        .collect()
+}
+
+fn mean(pixels: &[[u8; 3]]) -> [u8; 3] {
+    let mut sum = [0u64; 3];
+    for pixel in pixels {
+        for i in 0..3 {
+            sum[i] += pixel[i] as u64;
+        }
+    }
+    let count = pixels.len().max(1) as u64;
+    [
+        (sum[0] / count) as u8,
+        (sum[1] / count) as u8,
+        (sum[2] / count) as u8,
+    ]
+}
+
+/// Average colour of the visible pixels, `None` if the image is fully transparent.
+pub fn average_color(image: &RgbaImage) -> Option<[u8; 3]> {
+    let pixels = opaque_pixels(image);
+    if pixels.is_empty() {
+        return None;
+    }
+    Some(mean(&pixels))
+}
+
+/// Median-cut quantisation into at most `count` colours, each with the
+/// proportion of visible pixels it covers, most common first.
+pub fn dominant_colors(image: &RgbaImage, count: usize) -> Vec<([u8; 3], f32)> {
+    let pixels = opaque_pixels(image);
+    let total = pixels.len() as f32;
+    if pixels.is_empty() || count == 0 {
+        return vec![];
+    }
+
+    let mut boxes = vec![pixels];
+    while boxes.len() < count {
+        // Split the box with the widest channel range
+        let (index, channel, range) = boxes
+            .iter()
+            .enumerate()
+            .map(|(index, pixels)| {
+                let (channel, range) = widest_channel(pixels);
+                (index, channel, range)
+            })
+            .max_by_key(|(_, _, range)| *range)
+            .unwrap();
+        if range == 0 {
+            break;
+        }
+
+        let mut pixels = boxes.swap_remove(index);
+        pixels.sort_unstable_by_key(|p| p[channel]);
+        let upper = pixels.split_off(pixels.len() / 2);
+        boxes.push(pixels);
+        boxes.push(upper);
+    }
+
+    let mut colors: Vec<_> = boxes
+        .iter()
+        .map(|pixels| (mean(pixels), pixels.len() as f32 / total))
+        .collect();
+    colors.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+    colors
+}
+
+fn widest_channel(pixels: &[[u8; 3]]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let min = pixels.iter().map(|p| p[channel]).min().unwrap_or(0);
+            let max = pixels.iter().map(|p| p[channel]).max().unwrap_or(0);
+            (channel, max - min)
+        })
+        .max_by_key(|(_, range)| *range)
+        .unwrap()
+}
+
+/// Horizontal bars, one per colour, each as wide as its proportion.
+pub fn render_swatch(colors: &[([u8; 3], f32)], size: (u32, u32)) -> RgbImage {
+    let mut image = RgbImage::new(size.0, size.1);
+    let mut start = 0;
+    for (index, (color, proportion)) in colors.iter().enumerate() {
+        let end = if index == colors.len() - 1 {
+            size.0
+        } else {
+            (start + (proportion * size.0 as f32).round() as u32).min(size.0)
+        };
+        for x in start..end {
+            for y in 0..size.1 {
+                image.put_pixel(x, y, Rgb(*color));
+            }
+        }
+        start = end;
+    }
+    image
+}
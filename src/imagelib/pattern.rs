@@ -0,0 +1,33 @@
+use image::{Rgba, RgbaImage};
+
+use super::rng::Rng;
+
+pub fn checkerboard(colors: [[u8; 4]; 2], cell_size: u32, size: (u32, u32)) -> RgbaImage {
+    RgbaImage::from_fn(size.0, size.1, |x, y| {
+        Rgba(colors[((x / cell_size + y / cell_size) % 2) as usize])
+    })
+}
+
+/// Alternating stripes of `stripe_width` pixels, rotated by `angle` degrees.
+pub fn stripes(colors: [[u8; 4]; 2], stripe_width: u32, angle: f32, size: (u32, u32)) -> RgbaImage {
+    let (dx, dy) = (angle.to_radians().cos(), angle.to_radians().sin());
+
+    RgbaImage::from_fn(size.0, size.1, |x, y| {
+        let projected = x as f32 * dx + y as f32 * dy;
+        let band = (projected / stripe_width as f32).floor() as i64;
+        Rgba(colors[band.rem_euclid(2) as usize])
+    })
+}
+
+pub fn noise(seed: u64, monochrome: bool, size: (u32, u32)) -> RgbaImage {
+    let mut rng = Rng::new(seed);
+
+    RgbaImage::from_fn(size.0, size.1, |_, _| {
+        if monochrome {
+            let value = rng.next_u8();
+            Rgba([value, value, value, 255])
+        } else {
+            Rgba([rng.next_u8(), rng.next_u8(), rng.next_u8(), 255])
+        }
+    })
+}
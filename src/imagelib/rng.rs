@@ -0,0 +1,61 @@
+/// Small deterministic PRNG (SplitMix64), so seeded effects render the same
+/// output for the same seed on every platform.
+pub struct Rng(u64);
+
+impl Rng {
+    #[inline]
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    #[inline]
+    pub fn next_u8(&mut self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+
+    /// Uniform integer in `[low, high)`; returns `low` for an empty range.
+    pub fn range(&mut self, low: i64, high: i64) -> i64 {
+        if high <= low {
+            return low;
+        }
+        low + (self.next_u64() % (high - low) as u64) as i64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Rng;
+
+    #[test]
+    fn same_seed_same_sequence() {
+        let mut rng = Rng::new(42);
+        let values: Vec<u64> = (0..3).map(|_| rng.next_u64()).collect();
+        // Reference SplitMix64 output for seed 42
+        assert_eq!(
+            values,
+            [
+                13679457532755275413,
+                2949826092126892291,
+                5139283748462763858
+            ]
+        );
+    }
+
+    #[test]
+    fn range_stays_in_bounds() {
+        let mut rng = Rng::new(7);
+        for _ in 0..1000 {
+            let value = rng.range(-3, 4);
+            assert!((-3..4).contains(&value));
+        }
+        assert_eq!(rng.range(5, 5), 5);
+    }
+}
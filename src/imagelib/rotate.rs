@@ -0,0 +1,34 @@
+use image::{DynamicImage, Rgba, RgbaImage};
+use imageproc::geometric_transformations::{rotate_about_center, Interpolation};
+
+use super::fit::{pad, Gravity};
+
+/// Rotates clockwise by `degrees` about the centre, filling uncovered areas
+/// with `background`. With `expand` the canvas grows to fit the whole result.
+pub fn rotate(
+    image: RgbaImage,
+    degrees: f32,
+    expand: bool,
+    interpolation: Interpolation,
+    background: Rgba<u8>,
+) -> RgbaImage {
+    let theta = degrees.to_radians();
+    let image = if expand {
+        let (width, height) = (image.width() as f32, image.height() as f32);
+        let (sin, cos) = (theta.sin().abs(), theta.cos().abs());
+        let size = (
+            (width * cos + height * sin).ceil() as u32,
+            (width * sin + height * cos).ceil() as u32,
+        );
+        pad(
+            &DynamicImage::ImageRgba8(image),
+            size,
+            Gravity::Center,
+            background,
+        )
+        .to_rgba8()
+    } else {
+        image
+    };
+    rotate_about_center(&image, theta, interpolation, background)
+}
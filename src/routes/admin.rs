@@ -0,0 +1,79 @@
+use rocket::{
+    http::Status,
+    request::{self, FromRequest},
+    serde::json::{json, Value as JsonValue},
+    Request, State,
+};
+
+use crate::state::{asset_cache::AssetEntry, serverstate::ServerState};
+
+/// Guard for requests carrying `Authorization: Bearer <admin_token>`.
+/// Without a configured token the admin routes don't exist.
+pub struct Admin;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, ()> {
+        let state = request.rocket().state::<&ServerState>().unwrap();
+        let token = match &state.config.admin_token {
+            Some(token) => token,
+            None => return request::Outcome::Forward(()),
+        };
+        let authorization = request.headers().get_one("Authorization");
+        match authorization.and_then(|value| value.strip_prefix("Bearer ")) {
+            Some(given) if constant_time_eq(given.as_bytes(), token.as_bytes()) => {
+                request::Outcome::Success(Admin)
+            }
+            _ => request::Outcome::Failure((Status::Unauthorized, ())),
+        }
+    }
+}
+
+/// Compares without stopping at the first differing byte, so the time taken
+/// doesn't tell how much of the token was guessed.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn entries_json(entries: Vec<AssetEntry>) -> Vec<JsonValue> {
+    entries
+        .into_iter()
+        .map(|entry| {
+            json!({
+                "name": entry.name,
+                "size": entry.size,
+                "age": entry.age.as_secs(),
+            })
+        })
+        .collect()
+}
+
+#[get("/cache")]
+pub fn cache(_admin: Admin, state: &State<&'static ServerState>) -> JsonValue {
+    let (hits, misses) = state.cache.stats();
+    let mut response = json!({
+        "hits": hits,
+        "misses": misses,
+        "size": state.cache.size(),
+    });
+    for (kind, entries) in state.cache.entries() {
+        response[kind] = entries_json(entries).into();
+    }
+    response
+}
+
+/// Drops one asset by name, or all of them.
+#[delete("/cache?<name>")]
+pub fn purge(
+    name: Option<String>,
+    _admin: Admin,
+    state: &State<&'static ServerState>,
+) -> JsonValue {
+    json!({ "purged": state.cache.purge(name.as_deref()) })
+}
+
+pub fn routes() -> Vec<rocket::Route> {
+    routes![cache, purge]
+}
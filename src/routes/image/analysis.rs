@@ -0,0 +1,216 @@
+use image::{DynamicImage, GenericImageView};
+use rocket::{
+    serde::json::{json, Value as JsonValue},
+    State,
+};
+use tokio::task::spawn_blocking;
+
+use super::merge::TwoImages;
+use crate::{
+    datastructures::image::{Image, Sizing},
+    errors::Errors,
+    imagelib::{
+        compare::{diff_metrics, highlight_diff},
+        hash::{ahash, dhash, distance, phash},
+        image_response::ImageResponse,
+        info::image_info,
+        palette::{average_color, dominant_colors, render_swatch},
+    },
+    state::serverstate::ServerState,
+};
+
+/// Largest number of colours `/palette` will extract.
+const MAX_PALETTE_COLORS: usize = 16;
+/// Images are scaled to this before analysis, keeps median cut cheap.
+/// Stretching doesn't change the proportion of area each colour covers.
+const ANALYSIS_SIZE: u32 = 128;
+
+#[derive(Clone, Copy, FromFormField)]
+pub enum HashAlgorithm {
+    #[field(value = "ahash")]
+    Average,
+    #[field(value = "dhash")]
+    Difference,
+    #[field(value = "phash")]
+    Perceptual,
+}
+
+#[derive(Responder)]
+pub enum AnalysisResponse {
+    Json(JsonValue),
+    Image(ImageResponse),
+}
+
+fn color_json(color: [u8; 3]) -> JsonValue {
+    let [r, g, b] = color;
+    json!({
+        "rgb": [r, g, b],
+        "hex": format!("#{:02x}{:02x}{:02x}", r, g, b),
+        "int": (r as u32) << 16 | (g as u32) << 8 | b as u32,
+    })
+}
+
+#[post("/palette?<count>&<swatch>&<width>&<height>", data = "<image>")]
+pub async fn palette(
+    count: Option<usize>,
+    swatch: Option<bool>,
+    width: Option<u32>,
+    height: Option<u32>,
+    image: Image<'_>,
+    state: &State<&'static ServerState>,
+) -> Result<AnalysisResponse, Errors> {
+    let count = count.unwrap_or(5);
+    if count == 0 || count > MAX_PALETTE_COLORS {
+        return Err(Errors::InvalidInput(format!(
+            "Color count must be between 1 and {}",
+            MAX_PALETTE_COLORS
+        )));
+    }
+    let swatch_size = state.config.generated_image_size(width, height)?;
+
+    let image = image?
+        .to_image(Sizing::Exact(ANALYSIS_SIZE, ANALYSIS_SIZE), state)
+        .await?
+        .to_rgba8();
+    let (average, colors) =
+        spawn_blocking(move || (average_color(&image), dominant_colors(&image, count))).await?;
+
+    if swatch.unwrap_or(false) {
+        let image = spawn_blocking(move || render_swatch(&colors, swatch_size)).await?;
+        return Ok(AnalysisResponse::Image(ImageResponse(
+            DynamicImage::ImageRgb8(image),
+        )));
+    }
+
+    Ok(AnalysisResponse::Json(json!({
+        "average": average.map(color_json),
+        "colors": colors
+            .iter()
+            .map(|(color, proportion)| {
+                let mut color = color_json(*color);
+                color["proportion"] = json!(proportion);
+                color
+            })
+            .collect::<Vec<_>>(),
+    })))
+}
+
+#[post("/info", data = "<image>")]
+pub async fn info(
+    image: Image<'_>,
+    state: &State<&'static ServerState>,
+) -> Result<JsonValue, Errors> {
+    let bytes = image?.to_vec(0, state).await?;
+    let byte_size = bytes.len();
+    let state: &'static ServerState = state.inner();
+    let info = spawn_blocking(move || image_info(&bytes, &state.config)).await??;
+
+    Ok(json!({
+        "format": format!("{:?}", info.format).to_lowercase(),
+        "width": info.dimensions.0,
+        "height": info.dimensions.1,
+        "color_type": format!("{:?}", info.color_type).to_lowercase(),
+        "frame_count": info.frame_durations.len().max(1),
+        "frame_durations": info.frame_durations,
+        "byte_size": byte_size,
+        "transparent": info.transparent,
+    }))
+}
+
+#[post("/diff?<threshold>&<highlight>", data = "<two_images>")]
+pub async fn diff(
+    threshold: Option<u8>,
+    highlight: Option<bool>,
+    two_images: TwoImages<'_>,
+    state: &State<&'static ServerState>,
+) -> Result<AnalysisResponse, Errors> {
+    let threshold = threshold.unwrap_or(0);
+    let (first, second) = two_images?.to_images(Sizing::Native, state).await?;
+
+    // Differently sized inputs are compared at the size of the first one
+    let size = first.dimensions();
+    let resized = second.dimensions() != size;
+    let filter = state.config.resize_filtertype();
+    let second = if resized {
+        spawn_blocking(move || second.resize_exact(size.0, size.1, filter)).await?
+    } else {
+        second
+    };
+
+    if highlight.unwrap_or(false) {
+        let image = spawn_blocking(move || {
+            highlight_diff(&first.to_rgba8(), &second.to_rgba8(), threshold)
+        })
+        .await?;
+        return Ok(AnalysisResponse::Image(ImageResponse(
+            DynamicImage::ImageRgba8(image),
+        )));
+    }
+
+    let (metrics, hash_distance) = spawn_blocking(move || {
+        (
+            diff_metrics(&first.to_rgba8(), &second.to_rgba8(), threshold),
+            distance(phash(&first), phash(&second)),
+        )
+    })
+    .await?;
+    let pixels = size.0 as u64 * size.1 as u64;
+
+    Ok(AnalysisResponse::Json(json!({
+        "width": size.0,
+        "height": size.1,
+        "resized": resized,
+        "differing_pixels": metrics.differing_pixels,
+        "differing_ratio": metrics.differing_pixels as f64 / pixels.max(1) as f64,
+        "rmse": metrics.rmse,
+        "psnr": metrics.psnr,
+        "ssim": metrics.ssim,
+        "phash_distance": hash_distance,
+    })))
+}
+
+#[post("/hash?<compare>&<algorithm>", data = "<image>")]
+pub async fn hash(
+    compare: Option<String>,
+    algorithm: Option<HashAlgorithm>,
+    image: Image<'_>,
+    state: &State<&'static ServerState>,
+) -> Result<JsonValue, Errors> {
+    let compare = compare
+        .map(|hex| {
+            let digits = hex.strip_prefix("0x").unwrap_or(&hex);
+            // `from_str_radix` alone would also take a sign
+            if digits.is_empty()
+                || digits.len() > 16
+                || !digits.bytes().all(|byte| byte.is_ascii_hexdigit())
+            {
+                return Err(Errors::InvalidInput(format!("Invalid hash: {:?}", hex)));
+            }
+            Ok(u64::from_str_radix(digits, 16).unwrap())
+        })
+        .transpose()?;
+    let algorithm = algorithm.unwrap_or(HashAlgorithm::Perceptual);
+
+    let image = image?.to_image(Sizing::Native, state).await?;
+    let hashes = spawn_blocking(move || [ahash(&image), dhash(&image), phash(&image)]).await?;
+
+    let mut response = json!({
+        "ahash": format!("{:016x}", hashes[0]),
+        "dhash": format!("{:016x}", hashes[1]),
+        "phash": format!("{:016x}", hashes[2]),
+    });
+    if let Some(other) = compare {
+        let (name, own) = match algorithm {
+            HashAlgorithm::Average => ("ahash", hashes[0]),
+            HashAlgorithm::Difference => ("dhash", hashes[1]),
+            HashAlgorithm::Perceptual => ("phash", hashes[2]),
+        };
+        let distance = distance(own, other);
+        response["comparison"] = json!({
+            "algorithm": name,
+            "distance": distance,
+            "similarity": 1.0 - distance as f64 / 64.0,
+        });
+    }
+    Ok(response)
+}
@@ -3,9 +3,16 @@ use rocket::State;
 use tokio::task::spawn_blocking;
 
 use crate::{
-    datastructures::image::Image,
+    datastructures::{
+        generator::{GradientInput, PatternInput},
+        image::{Image, Sizing},
+    },
     errors::Errors,
-    imagelib::{fillcolor::fill_color, image_response::ImageResponse},
+    imagelib::{
+        blend::{blend_color, BlendSpace},
+        fillcolor::fill_color,
+        image_response::ImageResponse,
+    },
     state::serverstate::ServerState,
 };
 
@@ -22,30 +29,59 @@ pub async fn color(
     ImageResponse(DynamicImage::ImageRgb8(img)).ok()
 }
 
-#[post("/colorblend?<r>&<g>&<b>", data = "<image>")]
+#[post("/gradient?<width>&<height>", data = "<gradient>")]
+pub async fn gradient(
+    width: Option<u32>,
+    height: Option<u32>,
+    gradient: GradientInput<'_>,
+    state: &State<&'static ServerState>,
+) -> Result<ImageResponse, Errors> {
+    let gradient = gradient?.into_inner();
+    gradient.validate()?;
+    let size = state.config.generated_image_size(width, height)?;
+    let img = spawn_blocking(move || gradient.render(size)).await?;
+    ImageResponse(DynamicImage::ImageRgba8(img)).ok()
+}
+
+#[post("/pattern?<width>&<height>", data = "<pattern>")]
+pub async fn pattern(
+    width: Option<u32>,
+    height: Option<u32>,
+    pattern: PatternInput<'_>,
+    state: &State<&'static ServerState>,
+) -> Result<ImageResponse, Errors> {
+    let pattern = pattern?.into_inner();
+    pattern.validate()?;
+    let size = state.config.generated_image_size(width, height)?;
+    let img = spawn_blocking(move || pattern.render(size)).await?;
+    ImageResponse(DynamicImage::ImageRgba8(img)).ok()
+}
+
+#[post("/colorblend?<r>&<g>&<b>&<amount>&<space>", data = "<image>")]
 pub async fn blend(
     r: u8,
     g: u8,
     b: u8,
+    amount: Option<f32>,
+    space: Option<BlendSpace>,
     image: Image<'_>,
     state: &State<&'static ServerState>,
 ) -> Result<ImageResponse, Errors> {
-    let size = 256;
+    let amount = amount.unwrap_or(0.5);
+    if !(0.0..=1.0).contains(&amount) {
+        return Err(Errors::InvalidInput(
+            "Blend amount must be between 0 and 1".into(),
+        ));
+    }
+    let space = space.unwrap_or(BlendSpace::Rgb);
     let color = [r, g, b];
 
-    let image = image?.to_image(size, state).await?;
+    let image = image?.to_image(Sizing::Native, state).await?;
     let image = spawn_blocking(move || {
-        let mut image = image.to_rgb8();
-        for x in 0..size {
-            for y in 0..size {
-                let pixel = image.get_pixel_mut(x, y);
-                for i in 0..3 {
-                    pixel[i] = pixel[i] / 2 + color[i] / 2;
-                }
-            }
-        }
+        let mut image = image.to_rgba8();
+        blend_color(&mut image, color, amount, space);
         image
     })
     .await?;
-    ImageResponse(DynamicImage::ImageRgb8(image)).ok()
+    ImageResponse(DynamicImage::ImageRgba8(image)).ok()
 }
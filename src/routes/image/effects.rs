@@ -0,0 +1,163 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use image::{DynamicImage, RgbaImage};
+use rocket::State;
+use tokio::task::spawn_blocking;
+
+use super::in_range;
+use crate::{
+    datastructures::image::{Image, Sizing},
+    errors::Errors,
+    imagelib::{
+        effects,
+        image_response::{encode_gif, AnimationResponse, ImageResponse},
+    },
+    state::serverstate::ServerState,
+};
+
+const INPUT_SIZE: u32 = 256;
+const MAX_FRAMES: u32 = 60;
+
+/// Given seeds make the effects reproducible, otherwise every request differs.
+fn seed_or_random(seed: Option<u64>) -> u64 {
+    seed.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_nanos() as u64)
+            .unwrap_or_default()
+    })
+}
+
+async fn input(image: Image<'_>, state: &'static ServerState) -> Result<RgbaImage, Errors> {
+    Ok(image?
+        .to_image(Sizing::square(INPUT_SIZE), state)
+        .await?
+        .to_rgba8())
+}
+
+#[post("/deepfry?<seed>", data = "<image>")]
+pub async fn deepfry(
+    seed: Option<u64>,
+    image: Image<'_>,
+    state: &State<&'static ServerState>,
+) -> Result<ImageResponse, Errors> {
+    let seed = seed_or_random(seed);
+    let image = input(image, state).await?;
+    let image = spawn_blocking(move || effects::deepfry(&image, seed)).await??;
+    ImageResponse(DynamicImage::ImageRgba8(image)).ok()
+}
+
+#[post("/jpegcrush?<quality>", data = "<image>")]
+pub async fn jpegcrush(
+    quality: Option<u8>,
+    image: Image<'_>,
+    state: &State<&'static ServerState>,
+) -> Result<ImageResponse, Errors> {
+    let quality = in_range("quality", quality.unwrap_or(5), 1..=100)?;
+    let image = input(image, state).await?;
+    let image = spawn_blocking(move || effects::jpeg_crush(&image, quality)).await??;
+    ImageResponse(DynamicImage::ImageRgba8(image)).ok()
+}
+
+#[post("/glitch?<seed>&<amount>", data = "<image>")]
+pub async fn glitch(
+    seed: Option<u64>,
+    amount: Option<u32>,
+    image: Image<'_>,
+    state: &State<&'static ServerState>,
+) -> Result<ImageResponse, Errors> {
+    let seed = seed_or_random(seed);
+    let amount = in_range("amount", amount.unwrap_or(6), 0..=64)?;
+    let image = input(image, state).await?;
+    let image = spawn_blocking(move || effects::glitch(&image, seed, amount)).await?;
+    ImageResponse(DynamicImage::ImageRgba8(image)).ok()
+}
+
+#[post("/swirl?<degrees>", data = "<image>")]
+pub async fn swirl(
+    degrees: Option<f32>,
+    image: Image<'_>,
+    state: &State<&'static ServerState>,
+) -> Result<ImageResponse, Errors> {
+    let degrees = in_range("degrees", degrees.unwrap_or(270.0), -1080.0..=1080.0)?;
+    let image = input(image, state).await?;
+    let image = spawn_blocking(move || effects::swirl(&image, degrees)).await?;
+    ImageResponse(DynamicImage::ImageRgba8(image)).ok()
+}
+
+#[post("/bulge?<strength>", data = "<image>")]
+pub async fn bulge(
+    strength: Option<f32>,
+    image: Image<'_>,
+    state: &State<&'static ServerState>,
+) -> Result<ImageResponse, Errors> {
+    let strength = in_range("strength", strength.unwrap_or(0.6), -1.0..=1.0)?;
+    let image = input(image, state).await?;
+    let image = spawn_blocking(move || effects::bulge(&image, strength)).await?;
+    ImageResponse(DynamicImage::ImageRgba8(image)).ok()
+}
+
+#[post("/spread?<seed>&<radius>", data = "<image>")]
+pub async fn spread(
+    seed: Option<u64>,
+    radius: Option<u32>,
+    image: Image<'_>,
+    state: &State<&'static ServerState>,
+) -> Result<ImageResponse, Errors> {
+    let seed = seed_or_random(seed);
+    let radius = in_range("radius", radius.unwrap_or(4), 1..=32)?;
+    let image = input(image, state).await?;
+    let image = spawn_blocking(move || effects::spread(&image, seed, radius)).await?;
+    ImageResponse(DynamicImage::ImageRgba8(image)).ok()
+}
+
+#[post("/magik?<scale>", data = "<image>")]
+pub async fn magik(
+    scale: Option<f32>,
+    image: Image<'_>,
+    state: &State<&'static ServerState>,
+) -> Result<ImageResponse, Errors> {
+    let scale = in_range("scale", scale.unwrap_or(0.5), 0.1..=0.9)?;
+    let image = input(image, state).await?;
+    let image = spawn_blocking(move || effects::magik(&image, scale)).await?;
+    ImageResponse(DynamicImage::ImageRgba8(image)).ok()
+}
+
+#[post("/shake?<seed>&<intensity>&<frames>&<delay>", data = "<image>")]
+pub async fn shake(
+    seed: Option<u64>,
+    intensity: Option<u32>,
+    frames: Option<u32>,
+    delay: Option<u32>,
+    image: Image<'_>,
+    state: &State<&'static ServerState>,
+) -> Result<AnimationResponse, Errors> {
+    let seed = seed_or_random(seed);
+    let intensity = in_range("intensity", intensity.unwrap_or(8), 1..=64)?;
+    let frames = in_range("frames", frames.unwrap_or(12), 2..=MAX_FRAMES)?;
+    let delay_ms = in_range("delay", delay.unwrap_or(40), 20..=1000)?;
+    let image = input(image, state).await?;
+    let bytes = spawn_blocking(move || {
+        encode_gif(
+            effects::shake_frames(&image, seed, intensity, frames),
+            delay_ms,
+        )
+    })
+    .await??;
+    AnimationResponse(bytes).ok()
+}
+
+#[post("/spin?<frames>&<delay>", data = "<image>")]
+pub async fn spin(
+    frames: Option<u32>,
+    delay: Option<u32>,
+    image: Image<'_>,
+    state: &State<&'static ServerState>,
+) -> Result<AnimationResponse, Errors> {
+    let frames = in_range("frames", frames.unwrap_or(24), 2..=MAX_FRAMES)?;
+    let delay_ms = in_range("delay", delay.unwrap_or(40), 20..=1000)?;
+    let image = input(image, state).await?;
+    let bytes = spawn_blocking(move || encode_gif(effects::spin_frames(&image, frames), delay_ms))
+        .await??;
+    AnimationResponse(bytes).ok()
+}
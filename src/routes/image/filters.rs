@@ -0,0 +1,309 @@
+use image::DynamicImage;
+use imageproc::{
+    contrast::{otsu_level, threshold as threshold_image},
+    edges::canny,
+    filter::median_filter,
+};
+use rocket::State;
+use serde::Deserialize;
+use tokio::task::spawn_blocking;
+
+use super::in_range;
+use crate::{
+    datastructures::{
+        image::{Image, ImageJson, Sizing},
+        upload::{ImageInputs, WithUploads},
+    },
+    errors::Errors,
+    imagelib::{
+        filters,
+        image_response::{CachedImageResponse, ImageResponse},
+    },
+    state::{render_cache::RenderKey, serverstate::ServerState},
+};
+
+const INPUT_SIZE: u32 = 256;
+
+#[derive(Debug, Clone, Copy, FromFormField)]
+pub enum EdgeMethod {
+    Sobel,
+    Canny,
+}
+
+#[derive(Debug, Clone, Copy, FromFormField)]
+pub enum DenoiseMethod {
+    Median,
+    Bilateral,
+}
+
+/// Fetches the input and runs `filter` on it off the async runtime, unless
+/// the render cache already has the result for `key` and this input.
+async fn apply<F>(
+    image: Image<'_>,
+    state: &'static ServerState,
+    key: RenderKey,
+    filter: F,
+) -> Result<CachedImageResponse, Errors>
+where
+    F: FnOnce(DynamicImage) -> DynamicImage + Send + 'static,
+{
+    let image = image?;
+    let source = image.resolve(Sizing::square(INPUT_SIZE), state).await?;
+    let key = key.input(&source);
+    state
+        .render_cache
+        .get_or_render(key, async move {
+            let image = source.into_image(state).await?;
+            Ok(spawn_blocking(move || filter(image)).await?)
+        })
+        .await
+}
+
+#[post("/sharpen?<sigma>&<threshold>", data = "<image>")]
+pub async fn sharpen(
+    sigma: Option<f32>,
+    threshold: Option<i32>,
+    image: Image<'_>,
+    state: &State<&'static ServerState>,
+) -> Result<CachedImageResponse, Errors> {
+    let sigma = in_range("sigma", sigma.unwrap_or(1.0), 0.1..=20.0)?;
+    let threshold = in_range("threshold", threshold.unwrap_or(0), 0..=255)?;
+    let key = state
+        .render_cache
+        .key("/sharpen")
+        .param("sigma", sigma)
+        .param("threshold", threshold);
+    apply(image, state, key, move |image| {
+        image.unsharpen(sigma, threshold)
+    })
+    .await
+}
+
+#[post("/brightness?<value>", data = "<image>")]
+pub async fn brightness(
+    value: i32,
+    image: Image<'_>,
+    state: &State<&'static ServerState>,
+) -> Result<CachedImageResponse, Errors> {
+    let value = in_range("value", value, -255..=255)?;
+    let key = state.render_cache.key("/brightness").param("value", value);
+    apply(image, state, key, move |image| image.brighten(value)).await
+}
+
+#[post("/contrast?<value>", data = "<image>")]
+pub async fn contrast(
+    value: f32,
+    image: Image<'_>,
+    state: &State<&'static ServerState>,
+) -> Result<CachedImageResponse, Errors> {
+    let value = in_range("value", value, -100.0..=100.0)?;
+    let key = state.render_cache.key("/contrast").param("value", value);
+    apply(image, state, key, move |image| image.adjust_contrast(value)).await
+}
+
+#[post("/huerotate?<degrees>", data = "<image>")]
+pub async fn huerotate(
+    degrees: i32,
+    image: Image<'_>,
+    state: &State<&'static ServerState>,
+) -> Result<CachedImageResponse, Errors> {
+    let degrees = in_range("degrees", degrees, -360..=360)?;
+    let key = state
+        .render_cache
+        .key("/huerotate")
+        .param("degrees", degrees);
+    apply(image, state, key, move |image| image.huerotate(degrees)).await
+}
+
+#[post("/sepia", data = "<image>")]
+pub async fn sepia(
+    image: Image<'_>,
+    state: &State<&'static ServerState>,
+) -> Result<CachedImageResponse, Errors> {
+    let key = state.render_cache.key("/sepia");
+    apply(image, state, key, |image| {
+        let mut image = image.to_rgba8();
+        filters::sepia(&mut image);
+        DynamicImage::ImageRgba8(image)
+    })
+    .await
+}
+
+#[post("/posterize?<levels>", data = "<image>")]
+pub async fn posterize(
+    levels: Option<u8>,
+    image: Image<'_>,
+    state: &State<&'static ServerState>,
+) -> Result<CachedImageResponse, Errors> {
+    let levels = in_range("levels", levels.unwrap_or(4), 2..=64)?;
+    let key = state.render_cache.key("/posterize").param("levels", levels);
+    apply(image, state, key, move |image| {
+        let mut image = image.to_rgba8();
+        filters::posterize(&mut image, levels);
+        DynamicImage::ImageRgba8(image)
+    })
+    .await
+}
+
+#[post("/pixelate?<size>", data = "<image>")]
+pub async fn pixelate(
+    size: Option<u32>,
+    image: Image<'_>,
+    state: &State<&'static ServerState>,
+) -> Result<CachedImageResponse, Errors> {
+    let size = in_range("size", size.unwrap_or(8), 2..=INPUT_SIZE)?;
+    let key = state.render_cache.key("/pixelate").param("size", size);
+    apply(image, state, key, move |image| {
+        DynamicImage::ImageRgba8(filters::pixelate(&image.to_rgba8(), size))
+    })
+    .await
+}
+
+#[post("/edges?<method>&<low>&<high>", data = "<image>")]
+pub async fn edges(
+    method: Option<EdgeMethod>,
+    low: Option<f32>,
+    high: Option<f32>,
+    image: Image<'_>,
+    state: &State<&'static ServerState>,
+) -> Result<CachedImageResponse, Errors> {
+    let low = in_range("low", low.unwrap_or(50.0), 0.0..=1000.0)?;
+    let high = in_range("high", high.unwrap_or(100.0), low..=1000.0)?;
+    let method = method.unwrap_or(EdgeMethod::Sobel);
+    let key = state
+        .render_cache
+        .key("/edges")
+        .param("method", method)
+        .param("low", low)
+        .param("high", high);
+    apply(image, state, key, move |image| {
+        let image = image.to_luma8();
+        DynamicImage::ImageLuma8(match method {
+            EdgeMethod::Sobel => filters::sobel_edges(&image),
+            EdgeMethod::Canny => canny(&image, low, high),
+        })
+    })
+    .await
+}
+
+#[post("/emboss", data = "<image>")]
+pub async fn emboss(
+    image: Image<'_>,
+    state: &State<&'static ServerState>,
+) -> Result<CachedImageResponse, Errors> {
+    let key = state.render_cache.key("/emboss");
+    apply(image, state, key, |image| {
+        DynamicImage::ImageRgba8(filters::emboss(&image.to_rgba8()))
+    })
+    .await
+}
+
+/// Black and white threshold, picks a level with Otsu's method if none is given.
+#[post("/threshold?<level>", data = "<image>")]
+pub async fn threshold(
+    level: Option<u8>,
+    image: Image<'_>,
+    state: &State<&'static ServerState>,
+) -> Result<CachedImageResponse, Errors> {
+    let key = state.render_cache.key("/threshold").param("level", level);
+    apply(image, state, key, move |image| {
+        let image = image.to_luma8();
+        let level = level.unwrap_or_else(|| otsu_level(&image));
+        DynamicImage::ImageLuma8(threshold_image(&image, level))
+    })
+    .await
+}
+
+#[post("/denoise?<method>&<radius>", data = "<image>")]
+pub async fn denoise(
+    method: Option<DenoiseMethod>,
+    radius: Option<u32>,
+    image: Image<'_>,
+    state: &State<&'static ServerState>,
+) -> Result<CachedImageResponse, Errors> {
+    let radius = in_range("radius", radius.unwrap_or(2), 1..=8)?;
+    let method = method.unwrap_or(DenoiseMethod::Median);
+    let key = state
+        .render_cache
+        .key("/denoise")
+        .param("method", method)
+        .param("radius", radius);
+    apply(image, state, key, move |image| {
+        let image = image.to_rgba8();
+        DynamicImage::ImageRgba8(match method {
+            DenoiseMethod::Median => median_filter(&image, radius, radius),
+            DenoiseMethod::Bilateral => filters::bilateral(&image, radius, 30.0, radius as f32),
+        })
+    })
+    .await
+}
+
+#[derive(Deserialize)]
+pub struct ConvolveJson {
+    image: ImageJson,
+    /// Square, odd sized kernel given as rows
+    kernel: Vec<Vec<f32>>,
+    /// Defaults to the kernel sum, or 1 if that is 0
+    divisor: Option<f32>,
+    #[serde(default)]
+    bias: f32,
+}
+type ConvolveInput<'a> = Result<WithUploads<ConvolveJson>, Errors>;
+
+impl ImageInputs for ConvolveJson {
+    fn images_mut(&mut self) -> Vec<&mut ImageJson> {
+        vec![&mut self.image]
+    }
+}
+
+#[post("/convolve", data = "<input>")]
+pub async fn convolve(
+    input: ConvolveInput<'_>,
+    state: &State<&'static ServerState>,
+) -> Result<ImageResponse, Errors> {
+    let input = input?.into_inner();
+    let size = input.kernel.len();
+    let max_size = state.config.convolve_max_kernel_size;
+    let max_value = state.config.convolve_max_kernel_value;
+
+    if size % 2 == 0 || size > max_size || input.kernel.iter().any(|row| row.len() != size) {
+        return Err(Errors::InvalidInput(format!(
+            "Kernel must be square with an odd size of at most {}",
+            max_size
+        )));
+    }
+    let kernel: Vec<f32> = input.kernel.into_iter().flatten().collect();
+    if kernel
+        .iter()
+        .any(|value| !value.is_finite() || value.abs() > max_value)
+    {
+        return Err(Errors::InvalidInput(format!(
+            "Kernel values must be between -{0} and {0}",
+            max_value
+        )));
+    }
+    let divisor = input.divisor.unwrap_or_else(|| {
+        let sum: f32 = kernel.iter().sum();
+        if sum == 0.0 {
+            1.0
+        } else {
+            sum
+        }
+    });
+    if divisor == 0.0 || !divisor.is_finite() || !input.bias.is_finite() {
+        return Err(Errors::InvalidInput(
+            "Divisor must be non-zero and bias finite".into(),
+        ));
+    }
+    let bias = input.bias;
+
+    let image = input
+        .image
+        .to_image(Sizing::square(INPUT_SIZE), state)
+        .await?;
+    let image = spawn_blocking(move || {
+        filters::convolve(&image.to_rgba8(), &kernel, size as u32, divisor, bias)
+    })
+    .await?;
+    ImageResponse(DynamicImage::ImageRgba8(image)).ok()
+}
@@ -0,0 +1,197 @@
+use image::{DynamicImage, GenericImageView, Rgba};
+use imageproc::geometric_transformations::Interpolation as RotateInterpolation;
+use rocket::State;
+use tokio::task::spawn_blocking;
+
+use crate::{
+    datastructures::image::{Image, Sizing},
+    errors::Errors,
+    imagelib::{
+        fillcolor::parse_hex_color,
+        fit::{crop as crop_image, pad as pad_image, Fit, Gravity},
+        image_response::ImageResponse,
+        rotate::rotate as rotate_image,
+    },
+    state::serverstate::ServerState,
+};
+
+#[derive(FromFormField)]
+pub enum Interpolation {
+    Nearest,
+    Bilinear,
+    Bicubic,
+}
+
+impl From<Interpolation> for RotateInterpolation {
+    fn from(interpolation: Interpolation) -> Self {
+        match interpolation {
+            Interpolation::Nearest => Self::Nearest,
+            Interpolation::Bilinear => Self::Bilinear,
+            Interpolation::Bicubic => Self::Bicubic,
+        }
+    }
+}
+
+fn background(hex: Option<String>) -> Result<Rgba<u8>, Errors> {
+    Ok(Rgba(match hex {
+        Some(hex) => parse_hex_color(&hex)?,
+        None => [0; 4],
+    }))
+}
+
+#[post(
+    "/resize?<width>&<height>&<fit>&<gravity>&<background>",
+    data = "<image>"
+)]
+pub async fn resize(
+    width: Option<u32>,
+    height: Option<u32>,
+    fit: Option<Fit>,
+    gravity: Option<Gravity>,
+    background: Option<String>,
+    image: Image<'_>,
+    state: &State<&'static ServerState>,
+) -> Result<ImageResponse, Errors> {
+    let background = self::background(background)?;
+    let gravity = gravity.unwrap_or(Gravity::Center);
+    let max = state.config.output_image_max_size;
+
+    // With a single dimension the other one follows the aspect ratio
+    let (size, fit) = match (width, height) {
+        (Some(width), Some(height)) => ((width, height), fit.unwrap_or(Fit::Cover)),
+        (None, None) => {
+            return Err(Errors::InvalidInput(
+                "At least one of width and height is required".into(),
+            ))
+        }
+        (width, height) => {
+            if fit.unwrap_or(Fit::Inside) != Fit::Inside {
+                return Err(Errors::InvalidInput(
+                    "Both width and height are required for this fit mode".into(),
+                ));
+            }
+            ((width.unwrap_or(max), height.unwrap_or(max)), Fit::Inside)
+        }
+    };
+    let size = state.config.validate_output_size(size)?;
+
+    let image = image?
+        .to_image(
+            Sizing::Custom {
+                size,
+                fit,
+                gravity,
+                background,
+            },
+            state,
+        )
+        .await?;
+    ImageResponse(image).ok()
+}
+
+/// Whether `length` from `start` stays within `limit`, without overflowing.
+fn fits(start: u32, length: u32, limit: u32) -> bool {
+    matches!(start.checked_add(length), Some(end) if end <= limit)
+}
+
+#[post("/crop?<width>&<height>&<x>&<y>&<gravity>", data = "<image>")]
+pub async fn crop(
+    width: u32,
+    height: u32,
+    x: Option<u32>,
+    y: Option<u32>,
+    gravity: Option<Gravity>,
+    image: Image<'_>,
+    state: &State<&'static ServerState>,
+) -> Result<ImageResponse, Errors> {
+    let image = image?.to_image(Sizing::Native, state).await?;
+    let (image_width, image_height) = image.dimensions();
+    if width == 0 || height == 0 || width > image_width || height > image_height {
+        return Err(Errors::InvalidInput(format!(
+            "Crop size must be between 1x1 and the image size {}x{}",
+            image_width, image_height
+        )));
+    }
+
+    let image = match (x, y) {
+        (Some(x), Some(y)) => {
+            if !fits(x, width, image_width) || !fits(y, height, image_height) {
+                return Err(Errors::InvalidInput(
+                    "Crop region is outside the image".into(),
+                ));
+            }
+            spawn_blocking(move || image.crop_imm(x, y, width, height)).await?
+        }
+        (None, None) => {
+            let gravity = gravity.unwrap_or(Gravity::Center);
+            spawn_blocking(move || crop_image(&image, (width, height), gravity)).await?
+        }
+        _ => {
+            return Err(Errors::InvalidInput(
+                "Both x and y are required for a positioned crop".into(),
+            ))
+        }
+    };
+    ImageResponse(image).ok()
+}
+
+#[post("/pad?<width>&<height>&<gravity>&<background>", data = "<image>")]
+pub async fn pad(
+    width: u32,
+    height: u32,
+    gravity: Option<Gravity>,
+    background: Option<String>,
+    image: Image<'_>,
+    state: &State<&'static ServerState>,
+) -> Result<ImageResponse, Errors> {
+    let background = self::background(background)?;
+    let gravity = gravity.unwrap_or(Gravity::Center);
+    let size = state.config.validate_output_size((width, height))?;
+
+    let filter = state.config.resize_filtertype();
+    let image = image?.to_image(Sizing::Native, state).await?;
+    let image = spawn_blocking(move || {
+        // Only shrink images that don't fit, never enlarge them
+        let image = if image.width() > size.0 || image.height() > size.1 {
+            image.resize(size.0, size.1, filter)
+        } else {
+            image
+        };
+        pad_image(&image, size, gravity, background)
+    })
+    .await?;
+    ImageResponse(image).ok()
+}
+
+#[post(
+    "/rotate?<degrees>&<expand>&<interpolation>&<background>",
+    data = "<image>"
+)]
+pub async fn rotate(
+    degrees: f32,
+    expand: Option<bool>,
+    interpolation: Option<Interpolation>,
+    background: Option<String>,
+    image: Image<'_>,
+    state: &State<&'static ServerState>,
+) -> Result<ImageResponse, Errors> {
+    if !degrees.is_finite() {
+        return Err(Errors::InvalidInput("Invalid rotation angle".into()));
+    }
+    let background = self::background(background)?;
+    let expand = expand.unwrap_or(true);
+    let interpolation = interpolation.unwrap_or(Interpolation::Bilinear).into();
+
+    let image = image?.to_image(Sizing::Native, state).await?;
+    if expand {
+        // The expanded canvas is never wider or taller than the diagonal
+        let (width, height) = image.dimensions();
+        let diagonal = ((width as f32).hypot(height as f32)).ceil() as u32;
+        state.config.validate_output_size((diagonal, diagonal))?;
+    }
+    let image = spawn_blocking(move || {
+        rotate_image(image.to_rgba8(), degrees, expand, interpolation, background)
+    })
+    .await?;
+    ImageResponse(DynamicImage::ImageRgba8(image)).ok()
+}
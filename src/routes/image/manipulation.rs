@@ -1,7 +1,9 @@
 use rocket::State;
 
 use crate::{
-    datastructures::image::Image, errors::Errors, imagelib::image_response::ImageResponse,
+    datastructures::image::{Image, Sizing},
+    errors::Errors,
+    imagelib::image_response::CachedImageResponse,
     state::serverstate::ServerState,
 };
 
@@ -10,11 +12,18 @@ macro_rules! image_route {
         #[post($path, data = "<image>")]
         pub async fn $name(
             image: Image<'_>,
-            server_state: &State<&ServerState>,
-        ) -> Result<ImageResponse, Errors> {
-            let mut image = image?.to_image(256, &server_state).await?;
-            image.$method($(server_state.config.$args,)*);
-            ImageResponse(image).ok()
+            server_state: &State<&'static ServerState>,
+        ) -> Result<CachedImageResponse, Errors> {
+            let image = image?;
+            let source = image.resolve(Sizing::square(256), server_state).await?;
+            let key = server_state.render_cache.key($path)
+                $(.param(stringify!($args), server_state.config.$args))*
+                .input(&source);
+            server_state.render_cache.get_or_render(key, async move {
+                let mut image = source.into_image(server_state).await?;
+                image.$method($(server_state.config.$args,)*);
+                Ok(image)
+            }).await
         }
     };
 
@@ -22,13 +31,19 @@ macro_rules! image_route {
         #[post($path, data = "<image>")]
         pub async fn $name(
             image: Image<'_>,
-            server_state: &State<&ServerState>,
-        ) -> Result<ImageResponse, Errors> {
-            ImageResponse(image?
-                .to_image(256, &server_state)
-                .await?
-                .$method($(server_state.config.$args,)*))
-                .ok()
+            server_state: &State<&'static ServerState>,
+        ) -> Result<CachedImageResponse, Errors> {
+            let image = image?;
+            let source = image.resolve(Sizing::square(256), server_state).await?;
+            let key = server_state.render_cache.key($path)
+                $(.param(stringify!($args), server_state.config.$args))*
+                .input(&source);
+            server_state.render_cache.get_or_render(key, async move {
+                Ok(source
+                    .into_image(server_state)
+                    .await?
+                    .$method($(server_state.config.$args,)*))
+            }).await
         }
     };
 }
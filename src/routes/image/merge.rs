@@ -1,50 +1,66 @@
-use image::GenericImageView;
-use rocket::{
-    serde::json::{Error as JsonError, Json},
-    State,
-};
+use image::DynamicImage;
+use rocket::State;
 use serde::Deserialize;
 use tokio::task::spawn_blocking;
 
 use crate::{
-    datastructures::image::ImageJson, errors::Errors, imagelib::image_response::ImageResponse,
+    datastructures::{
+        collage::CollageInput,
+        image::{ImageJson, Sizing},
+        upload::{ImageInputs, WithUploads},
+    },
+    errors::Errors,
+    imagelib::image_response::ImageResponse,
     state::serverstate::ServerState,
 };
 
 #[derive(Deserialize)]
 pub struct TwoImagesJson([ImageJson; 2]);
-type TwoImages<'a> = Result<Json<TwoImagesJson>, JsonError<'a>>;
+pub type TwoImages<'a> = Result<WithUploads<TwoImagesJson>, Errors>;
+
+impl TwoImagesJson {
+    pub async fn to_images(
+        &self,
+        sizing: Sizing,
+        state: &'static ServerState,
+    ) -> Result<(DynamicImage, DynamicImage), Errors> {
+        let [first, second] = &self.0;
+        let mut images = ImageJson::to_images([(first, sizing), (second, sizing)], state).await?;
+        let second = images.pop().unwrap();
+        Ok((images.pop().unwrap(), second))
+    }
+}
+
+impl ImageInputs for TwoImagesJson {
+    fn images_mut(&mut self) -> Vec<&mut ImageJson> {
+        self.0.iter_mut().collect()
+    }
+}
 
 #[post("/merge", data = "<two_images>")]
 pub async fn merge(
     two_images: TwoImages<'_>,
     server_state: &State<&'static ServerState>,
 ) -> Result<ImageResponse, Errors> {
-    let two_images = two_images?;
-    let [base, layer] = two_images.into_inner().0;
-    let (mut base, mut layer) = (
-        base.to_image(256, server_state).await?,
-        layer.to_image(256, server_state).await?,
-    );
+    // Covering the same box gives both layers identical dimensions
+    let (base, layer) = two_images?
+        .to_images(Sizing::Cover(256, 256), server_state)
+        .await?;
 
     let image = spawn_blocking(move || {
-        let (b_x, b_y) = base.dimensions();
-        let (l_x, l_y) = layer.dimensions();
-        let x = *[b_x, l_x].iter().min().unwrap();
-        let y = *[b_y, l_y].iter().min().unwrap();
-
-        if x != b_x || y != b_x {
-            base = base.crop(0, 0, x, y);
-        }
-
-        if x != b_y || y != b_y {
-            layer = layer.crop(0, 0, x, y);
-        }
-
         imageproc::map::map_colors2(&base, &layer, |a, b| {
             imageproc::pixelops::weighted_sum(a, b, 0.5, 0.5)
         })
     })
     .await?;
-    ImageResponse(image::DynamicImage::ImageRgba8(image)).ok()
+    ImageResponse(DynamicImage::ImageRgba8(image)).ok()
+}
+
+#[post("/collage", data = "<collage>")]
+pub async fn collage(
+    collage: CollageInput<'_>,
+    state: &State<&'static ServerState>,
+) -> Result<ImageResponse, Errors> {
+    let image = collage?.into_inner().process(state).await?;
+    ImageResponse(image).ok()
 }
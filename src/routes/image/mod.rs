@@ -1,4 +1,12 @@
+use std::{fmt::Display, ops::RangeInclusive};
+
+use crate::errors::Errors;
+
+mod analysis;
 mod color;
+mod effects;
+mod filters;
+mod geometry;
 mod manipulation;
 mod merge;
 mod templates;
@@ -13,9 +21,58 @@ pub fn routes() -> Vec<rocket::Route> {
         manipulation::grayscale,
         manipulation::invert,
         manipulation::blur,
+        filters::sharpen,
+        filters::brightness,
+        filters::contrast,
+        filters::huerotate,
+        filters::sepia,
+        filters::posterize,
+        filters::pixelate,
+        filters::edges,
+        filters::emboss,
+        filters::threshold,
+        filters::denoise,
+        filters::convolve,
+        geometry::resize,
+        geometry::crop,
+        geometry::pad,
+        geometry::rotate,
+        effects::deepfry,
+        effects::jpegcrush,
+        effects::glitch,
+        effects::swirl,
+        effects::bulge,
+        effects::spread,
+        effects::magik,
+        effects::shake,
+        effects::spin,
         color::color,
+        color::gradient,
+        color::pattern,
         color::blend,
         merge::merge,
+        merge::collage,
         templates::template,
+        analysis::palette,
+        analysis::info,
+        analysis::diff,
+        analysis::hash,
     ]
 }
+
+/// Rejects query parameters outside of `range` with a descriptive error.
+fn in_range<T: PartialOrd + Display>(
+    name: &str,
+    value: T,
+    range: RangeInclusive<T>,
+) -> Result<T, Errors> {
+    if !range.contains(&value) {
+        return Err(Errors::InvalidInput(format!(
+            "{} must be between {} and {}",
+            name,
+            range.start(),
+            range.end()
+        )));
+    }
+    Ok(value)
+}
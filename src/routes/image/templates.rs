@@ -2,7 +2,7 @@ use rocket::State;
 
 use crate::{
     datastructures::template::TemplateInput, errors::Errors,
-    imagelib::image_response::ImageResponse, state::serverstate::ServerState,
+    imagelib::image_response::CachedImageResponse, state::serverstate::ServerState,
 };
 
 #[post("/template/<name>", data = "<template_input>")]
@@ -10,10 +10,17 @@ pub async fn template(
     name: String,
     server_state: &State<&'static ServerState>,
     template_input: TemplateInput<'_>,
-) -> Result<ImageResponse, Errors> {
+) -> Result<CachedImageResponse, Errors> {
     let template_input = template_input?.into_inner();
     let template = server_state.config.get_template(name)?;
     template.validate(&template_input)?;
-    let image = template.process(server_state, template_input).await?;
-    ImageResponse(image).ok()
+    let sources = template.resolve(server_state, &template_input).await?;
+    let key = template.render_key(server_state, &template_input, &sources);
+    server_state
+        .render_cache
+        .get_or_render(
+            key,
+            template.process(server_state, template_input.texts.clone(), sources),
+        )
+        .await
 }
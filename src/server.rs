@@ -1,3 +1,7 @@
+use figment::{
+    providers::{Env, Format, Serialized, Toml},
+    Figment, Profile,
+};
 use rocket::{shield::Shield, Build, Rocket};
 
 use crate::{fairings::response_time::RequestTimer, state::serverstate::ServerState};
@@ -10,8 +14,27 @@ lazy_static::lazy_static! {
 
 fn create_server() -> Rocket<Build> {
     let state: &ServerState = &STATE;
-    rocket::build()
+    let upload_max_bytes = state.config.upload_max_bytes;
+    // Headroom so an oversized file hits the `file` limit first
+    let form_max_bytes = state
+        .config
+        .upload_form_max_bytes
+        .max(upload_max_bytes + 1024 * 1024);
+    // Same as `rocket::Config::figment`, but with the upload limits as defaults
+    // so `limits` from Rocket.toml or `ROCKET_LIMITS` still take precedence
+    let figment = Figment::from(rocket::Config::default())
+        .merge(Serialized::default("limits.image", upload_max_bytes))
+        .merge(Serialized::default("limits.file", upload_max_bytes))
+        .merge(Serialized::default("limits.data-form", form_max_bytes))
+        .merge(Toml::file(Env::var_or("ROCKET_CONFIG", "Rocket.toml")).nested())
+        .merge(Env::prefixed("ROCKET_").ignore(&["PROFILE"]).global())
+        .select(Profile::from_env_or(
+            "ROCKET_PROFILE",
+            rocket::Config::DEFAULT_PROFILE,
+        ));
+    rocket::custom(figment)
         .mount("/", crate::routes::image::routes())
+        .mount("/admin", crate::routes::admin::routes())
         .manage(state)
         .attach(Shield::new())
         .attach(RequestTimer)
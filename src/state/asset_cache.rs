@@ -1,55 +1,219 @@
 use std::{
-    collections::HashMap,
-    sync::{Arc, RwLock},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
 };
 
+use image::DynamicImage;
 use rusttype::Font;
+use tokio::task::spawn_blocking;
 
+use super::lru::LruCache;
 use crate::errors::Errors;
 
+/// Size and modification time of an asset's file, `None` if it can't be read.
+pub type Stamp = Option<(u64, Option<SystemTime>)>;
+
+/// The current [`Stamp`] of the file at `name`.
+pub fn stamp(name: &str) -> Stamp {
+    let metadata = std::fs::metadata(name).ok()?;
+    Some((metadata.len(), metadata.modified().ok()))
+}
+
+struct Asset<T> {
+    value: Arc<T>,
+    loaded: Instant,
+    /// Taken before the file was read, so a change since then reloads it
+    stamp: Stamp,
+}
+
+/// A cached asset as listed by the admin routes.
+pub struct AssetEntry {
+    pub name: String,
+    pub size: usize,
+    pub age: Duration,
+}
+
+/// Template images and fonts read from disk, bounded by total bytes and
+/// reloaded once older than the TTL or changed on disk. Images are also kept decoded, in a
+/// separate budget since they're many times larger.
 pub struct AssetCache {
-    image_cache: RwLock<HashMap<String, Arc<Vec<u8>>>>,
-    font_cache: RwLock<HashMap<String, Arc<Font<'static>>>>,
+    image_cache: Mutex<LruCache<String, Asset<Vec<u8>>>>,
+    decoded_cache: Mutex<LruCache<String, Asset<DynamicImage>>>,
+    font_cache: Mutex<LruCache<String, Asset<Font<'static>>>>,
+    ttl: Duration,
+    hits: AtomicU64,
+    misses: AtomicU64,
 }
 
 impl AssetCache {
+    /// `max_bytes` is shared equally between images and fonts, decoded images
+    /// get `decoded_max_bytes` of their own.
     #[inline]
-    pub fn new() -> Self {
+    pub fn new(max_bytes: usize, decoded_max_bytes: usize, ttl: Duration) -> Self {
         Self {
-            image_cache: RwLock::new(HashMap::new()),
-            font_cache: RwLock::new(HashMap::new()),
+            image_cache: Mutex::new(LruCache::new(max_bytes / 2)),
+            decoded_cache: Mutex::new(LruCache::new(decoded_max_bytes)),
+            font_cache: Mutex::new(LruCache::new(max_bytes / 2)),
+            ttl,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
         }
     }
 
+    fn lookup<T>(&self, cache: &Mutex<LruCache<String, Asset<T>>>, name: &str) -> Option<Arc<T>> {
+        let current = stamp(name);
+        let mut cache = cache.lock().unwrap();
+        let value = match cache.get(name) {
+            Some(asset) if asset.loaded.elapsed() < self.ttl && asset.stamp == current => {
+                Some(Arc::clone(&asset.value))
+            }
+            Some(_) => {
+                cache.remove(name);
+                None
+            }
+            None => None,
+        };
+        let counter = if value.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        value
+    }
+
+    fn store<T>(
+        cache: &Mutex<LruCache<String, Asset<T>>>,
+        name: &str,
+        value: T,
+        size: usize,
+        stamp: Stamp,
+    ) -> Arc<T> {
+        let value = Arc::new(value);
+        cache.lock().unwrap().insert(
+            name.to_string(),
+            Asset {
+                value: Arc::clone(&value),
+                loaded: Instant::now(),
+                stamp,
+            },
+            size,
+        );
+        value
+    }
+
     pub async fn get_image(&self, name: &str) -> Result<Arc<Vec<u8>>, Errors> {
-        if let Some(arc) = self.image_cache.read().unwrap().get(name) {
-            return Ok(arc.clone());
+        if let Some(bytes) = self.lookup(&self.image_cache, name) {
+            return Ok(bytes);
+        }
+        let stamp = stamp(name);
+        let bytes = tokio::fs::read(name).await?;
+        let size = bytes.len();
+        Ok(Self::store(&self.image_cache, name, bytes, size, stamp))
+    }
+
+    /// The decoded image, shared with the cache. Callers that draw on it
+    /// copy it first, see [`AssetCache::get_decoded_owned`].
+    pub async fn get_decoded(&self, name: &str) -> Result<Arc<DynamicImage>, Errors> {
+        if let Some(image) = self.lookup(&self.decoded_cache, name) {
+            return Ok(image);
         }
-        let bytes = tokio::fs::read(name).await?.to_vec();
-        let arc = Arc::new(bytes);
-        self.image_cache
-            .write()
-            .unwrap()
-            .insert(name.to_string(), Arc::clone(&arc));
-        Ok(arc)
+        let stamp = stamp(name);
+        let bytes = self.get_image(name).await?;
+        let image = spawn_blocking(move || image::load_from_memory(&bytes)).await??;
+        let size = image.as_bytes().len();
+        Ok(Self::store(&self.decoded_cache, name, image, size, stamp))
+    }
+
+    /// The decoded image to modify, only copied when the cache holds on to it.
+    pub async fn get_decoded_owned(&self, name: &str) -> Result<DynamicImage, Errors> {
+        let image = self.get_decoded(name).await?;
+        Ok(Arc::try_unwrap(image).unwrap_or_else(|image| (*image).clone()))
     }
 
     pub fn get_font(&self, name: &str) -> Result<Arc<Font<'static>>, Errors> {
-        if let Some(font) = self.font_cache.read().unwrap().get(name) {
-            return Ok(font.clone());
+        if let Some(font) = self.lookup(&self.font_cache, name) {
+            return Ok(font);
         }
-        let font = Font::try_from_vec(std::fs::read(name)?.to_vec()).expect("Invalid font");
-        let arc = Arc::new(font);
-        self.font_cache
-            .write()
-            .unwrap()
-            .insert(name.to_string(), Arc::clone(&arc));
-        Ok(arc)
+        let stamp = stamp(name);
+        let bytes = std::fs::read(name)?;
+        let size = bytes.len();
+        let font = Font::try_from_vec(bytes).expect("Invalid font");
+        Ok(Self::store(&self.font_cache, name, font, size, stamp))
+    }
+
+    /// Loads assets ahead of the first request that needs them.
+    pub fn preload_image(&self, name: &str) -> Result<(), Errors> {
+        let stamp = stamp(name);
+        let bytes = std::fs::read(name)?;
+        let image = image::load_from_memory(&bytes)?;
+        let size = bytes.len();
+        Self::store(&self.image_cache, name, bytes, size, stamp);
+        let size = image.as_bytes().len();
+        Self::store(&self.decoded_cache, name, image, size, stamp);
+        Ok(())
+    }
+
+    /// `(hits, misses)` since startup.
+    pub fn stats(&self) -> (u64, u64) {
+        (
+            self.hits.load(Ordering::Relaxed),
+            self.misses.load(Ordering::Relaxed),
+        )
     }
-}
 
-impl Default for AssetCache {
-    fn default() -> Self {
-        Self::new()
+    /// Entries of every cache by kind, least recently used first.
+    pub fn entries(&self) -> [(&'static str, Vec<AssetEntry>); 3] {
+        fn list<T>(cache: &Mutex<LruCache<String, Asset<T>>>) -> Vec<AssetEntry> {
+            cache
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(name, asset, size)| AssetEntry {
+                    name: name.clone(),
+                    size,
+                    age: asset.loaded.elapsed(),
+                })
+                .collect()
+        }
+        [
+            ("images", list(&self.image_cache)),
+            ("decoded", list(&self.decoded_cache)),
+            ("fonts", list(&self.font_cache)),
+        ]
+    }
+
+    /// Total bytes of the cached images, decoded images and fonts.
+    pub fn size(&self) -> usize {
+        self.image_cache.lock().unwrap().size()
+            + self.decoded_cache.lock().unwrap().size()
+            + self.font_cache.lock().unwrap().size()
+    }
+
+    /// Drops the named asset, or everything without a name. Returns the
+    /// number of entries removed.
+    pub fn purge(&self, name: Option<&str>) -> usize {
+        let (mut images, mut decoded, mut fonts) = (
+            self.image_cache.lock().unwrap(),
+            self.decoded_cache.lock().unwrap(),
+            self.font_cache.lock().unwrap(),
+        );
+        match name {
+            Some(name) => {
+                images.remove(name).is_some() as usize
+                    + decoded.remove(name).is_some() as usize
+                    + fonts.remove(name).is_some() as usize
+            }
+            None => {
+                let count = images.iter().count() + decoded.iter().count() + fonts.iter().count();
+                images.clear();
+                decoded.clear();
+                fonts.clear();
+                count
+            }
+        }
     }
 }
@@ -1,4 +1,9 @@
-use std::sync::RwLock;
+use std::{
+    collections::HashMap,
+    path::{Component, Path, PathBuf},
+    sync::RwLock,
+    time::Duration,
+};
 
 use figment::{
     providers::{Format, Toml},
@@ -9,6 +14,38 @@ use serde::Deserialize;
 
 use crate::{datastructures::template::Template, errors::Errors};
 
+/// Download limits and fallback of one remote image source.
+#[derive(Deserialize)]
+pub struct SourceLimits {
+    #[serde(default = "default_value::source_max_bytes")]
+    pub max_bytes: usize,
+    /// Covers connecting and reading the whole body
+    #[serde(default = "default_value::source_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Serve the provider's default avatar for users or images that don't exist
+    #[serde(default)]
+    pub default_avatar: bool,
+}
+
+impl SourceLimits {
+    #[inline]
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_ms)
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Sources {
+    #[serde(default = "default_value::source_limits")]
+    pub discord: SourceLimits,
+    #[serde(default = "default_value::source_limits")]
+    pub github: SourceLimits,
+    #[serde(default = "default_value::source_limits")]
+    pub imgur: SourceLimits,
+    #[serde(default = "default_value::source_limits")]
+    pub url: SourceLimits,
+}
+
 #[derive(Deserialize)]
 pub struct ServerConfig {
     pub templates: Vec<Template>,
@@ -17,7 +54,86 @@ pub struct ServerConfig {
     pub textdraw_text_max_len: usize,
     pub blur_sigma: f32,
     pub colorfill_image_size: u32,
+    #[serde(default = "default_value::output_image_max_size")]
+    pub output_image_max_size: u32,
     pub allow_local_file_input: bool,
+    /// Directories `ImageJson::File` inputs can read from, by name
+    #[serde(default)]
+    pub asset_roots: HashMap<String, String>,
+    #[serde(default = "default_value::file_input_extensions")]
+    pub file_input_extensions: Vec<String>,
+    /// Enables `ImageJson::Url` inputs
+    #[serde(default)]
+    pub allow_url_input: bool,
+    /// Hosts URL inputs may use, including their subdomains. Empty allows any
+    /// public host not denied below
+    #[serde(default)]
+    pub url_allowed_hosts: Vec<String>,
+    #[serde(default)]
+    pub url_denied_hosts: Vec<String>,
+    #[serde(default = "default_value::url_max_redirects")]
+    pub url_max_redirects: usize,
+    #[serde(default = "default_value::sources")]
+    pub sources: Sources,
+    /// Largest raw or multipart image upload, unless Rocket's `limits` say otherwise
+    #[serde(default = "default_value::upload_max_bytes")]
+    pub upload_max_bytes: u64,
+    /// Largest whole multipart body, all files of a multi-image request together
+    #[serde(default = "default_value::upload_form_max_bytes")]
+    pub upload_form_max_bytes: u64,
+    /// Largest input dimensions accepted, checked from the header before decoding
+    #[serde(default = "default_value::decode_max_dimension")]
+    pub decode_max_width: u32,
+    #[serde(default = "default_value::decode_max_dimension")]
+    pub decode_max_height: u32,
+    #[serde(default = "default_value::decode_max_pixels")]
+    pub decode_max_pixels: u64,
+    /// Most frames scanned in an animated input
+    #[serde(default = "default_value::decode_max_frames")]
+    pub decode_max_frames: usize,
+    /// Estimated bytes all decoded inputs of one request may take together
+    #[serde(default = "default_value::request_memory_budget")]
+    pub request_memory_budget: u64,
+    #[serde(default = "default_value::convolve_max_kernel_size")]
+    pub convolve_max_kernel_size: usize,
+    #[serde(default = "default_value::convolve_max_kernel_value")]
+    pub convolve_max_kernel_value: f32,
+    /// Inputs fetched at once per request
+    #[serde(default = "default_value::fetch_concurrency")]
+    pub fetch_concurrency: usize,
+    /// Deadline for fetching all inputs of a request
+    #[serde(default = "default_value::fetch_deadline_ms")]
+    pub fetch_deadline_ms: u64,
+    /// Memory used by cached remote images
+    #[serde(default = "default_value::fetch_cache_max_bytes")]
+    pub fetch_cache_max_bytes: usize,
+    /// Also keeps remote images on disk when set
+    pub fetch_cache_dir: Option<String>,
+    #[serde(default = "default_value::fetch_cache_disk_max_bytes")]
+    pub fetch_cache_disk_max_bytes: usize,
+    /// Seconds a response without `Cache-Control: max-age` stays fresh
+    #[serde(default = "default_value::fetch_cache_default_ttl")]
+    pub fetch_cache_default_ttl: u64,
+    /// Memory used by cached renders, 0 disables the cache
+    #[serde(default = "default_value::render_cache_max_bytes")]
+    pub render_cache_max_bytes: usize,
+    /// Also keeps renders on disk when set
+    pub render_cache_dir: Option<String>,
+    #[serde(default = "default_value::render_cache_disk_max_bytes")]
+    pub render_cache_disk_max_bytes: usize,
+    /// Memory used by cached template images and fonts
+    #[serde(default = "default_value::asset_cache_max_bytes")]
+    pub asset_cache_max_bytes: usize,
+    /// Memory used by decoded template images, 0 decodes on every use
+    #[serde(default = "default_value::decoded_cache_max_bytes")]
+    pub decoded_cache_max_bytes: usize,
+    /// Seconds before a cached asset is read from disk again
+    #[serde(default = "default_value::asset_cache_ttl")]
+    pub asset_cache_ttl: u64,
+    /// Bearer token for the `/admin` routes, they're disabled without one
+    pub admin_token: Option<String>,
+    #[serde(default = "default_value::collage_max_images")]
+    pub collage_max_images: usize,
 
     #[serde(rename = "resize_filtertype")]
     resize_filtertype_string: String,
@@ -48,6 +164,105 @@ impl ServerConfig {
         Err(Errors::InvalidTemplate(name))
     }
 
+    /// Output size for generated images, defaulting to `colorfill_image_size`
+    pub fn generated_image_size(
+        &self,
+        width: Option<u32>,
+        height: Option<u32>,
+    ) -> Result<(u32, u32), Errors> {
+        self.validate_output_size((
+            width.unwrap_or(self.colorfill_image_size),
+            height.unwrap_or(self.colorfill_image_size),
+        ))
+    }
+
+    pub fn validate_output_size(&self, size: (u32, u32)) -> Result<(u32, u32), Errors> {
+        let max = self.output_image_max_size;
+        if size.0 == 0 || size.1 == 0 || size.0 > max || size.1 > max {
+            return Err(Errors::InvalidInput(format!(
+                "Image dimensions must be between 1 and {}",
+                max
+            )));
+        }
+        Ok(size)
+    }
+
+    /// Rejects input dimensions, read from the image header, that are too
+    /// large to decode.
+    pub fn check_decode_size(&self, (width, height): (u32, u32)) -> Result<(), Errors> {
+        if width > self.decode_max_width || height > self.decode_max_height {
+            return Err(Errors::ImageTooLarge(format!(
+                "Input images can be at most {}x{}, got {}x{}",
+                self.decode_max_width, self.decode_max_height, width, height
+            )));
+        }
+        if width as u64 * height as u64 > self.decode_max_pixels {
+            return Err(Errors::ImageTooLarge(format!(
+                "Input images can have at most {} pixels, got {}x{}",
+                self.decode_max_pixels, width, height
+            )));
+        }
+        Ok(())
+    }
+
+    /// Fails once the estimated decoded size of a request's inputs exceeds
+    /// `request_memory_budget`.
+    pub fn check_memory_budget(&self, bytes: u64) -> Result<(), Errors> {
+        if bytes > self.request_memory_budget {
+            return Err(Errors::ImageTooLarge(format!(
+                "The input images need about {} bytes decoded, the limit is {}",
+                bytes, self.request_memory_budget
+            )));
+        }
+        Ok(())
+    }
+
+    /// Resolves `path` inside the asset root named `root`. The result is
+    /// canonical, paths leaving the root, including through symlinks, are
+    /// rejected.
+    pub async fn asset_path(&self, root: &str, path: &str) -> Result<PathBuf, Errors> {
+        if !self.allow_local_file_input {
+            return Err(Errors::InvalidInput("Local file input is disabled.".into()));
+        }
+        let root = self
+            .asset_roots
+            .get(root)
+            .ok_or_else(|| Errors::InvalidInput(format!("Unknown asset root {:?}", root)))?;
+
+        let relative = Path::new(path);
+        if !relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(..)))
+        {
+            return Err(Errors::InvalidInput(
+                "File paths must be relative to the asset root".into(),
+            ));
+        }
+        let extension = relative
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+        if !matches!(extension, Some(extension) if self.file_input_extensions.contains(&extension))
+        {
+            return Err(Errors::InvalidInput(format!(
+                "File extension must be one of {}",
+                self.file_input_extensions.join(", ")
+            )));
+        }
+
+        let not_found = |_| Errors::InvalidInput(format!("File {:?} not found", path));
+        let root = tokio::fs::canonicalize(root).await.map_err(not_found)?;
+        let resolved = tokio::fs::canonicalize(root.join(relative))
+            .await
+            .map_err(not_found)?;
+        if !resolved.starts_with(&root) {
+            return Err(Errors::InvalidInput(
+                "File is outside of the asset root".into(),
+            ));
+        }
+        Ok(resolved)
+    }
+
     pub fn resize_filtertype(&self) -> FilterType {
         {
             let filtertype = self.resize_filtertype.read().unwrap();
@@ -67,3 +282,118 @@ impl ServerConfig {
         filtertype
     }
 }
+
+mod default_value {
+    pub fn upload_max_bytes() -> u64 {
+        8 * 1024 * 1024
+    }
+
+    pub fn upload_form_max_bytes() -> u64 {
+        32 * 1024 * 1024
+    }
+
+    pub fn decode_max_dimension() -> u32 {
+        8192
+    }
+
+    pub fn decode_max_pixels() -> u64 {
+        32 * 1024 * 1024
+    }
+
+    pub fn decode_max_frames() -> usize {
+        256
+    }
+
+    pub fn request_memory_budget() -> u64 {
+        512 * 1024 * 1024
+    }
+
+    pub fn convolve_max_kernel_size() -> usize {
+        5
+    }
+
+    pub fn convolve_max_kernel_value() -> f32 {
+        256.0
+    }
+
+    pub fn file_input_extensions() -> Vec<String> {
+        ["png", "jpg", "jpeg", "gif"].map(String::from).to_vec()
+    }
+
+    pub fn url_max_redirects() -> usize {
+        5
+    }
+
+    pub fn source_max_bytes() -> usize {
+        8 * 1024 * 1024
+    }
+
+    pub fn source_timeout_ms() -> u64 {
+        10000
+    }
+
+    pub fn source_limits() -> super::SourceLimits {
+        super::SourceLimits {
+            max_bytes: source_max_bytes(),
+            timeout_ms: source_timeout_ms(),
+            default_avatar: false,
+        }
+    }
+
+    pub fn sources() -> super::Sources {
+        super::Sources {
+            discord: source_limits(),
+            github: source_limits(),
+            imgur: source_limits(),
+            url: source_limits(),
+        }
+    }
+
+    pub fn output_image_max_size() -> u32 {
+        2048
+    }
+
+    pub fn fetch_concurrency() -> usize {
+        4
+    }
+
+    pub fn fetch_deadline_ms() -> u64 {
+        10000
+    }
+
+    pub fn fetch_cache_max_bytes() -> usize {
+        64 * 1024 * 1024
+    }
+
+    pub fn fetch_cache_disk_max_bytes() -> usize {
+        256 * 1024 * 1024
+    }
+
+    pub fn fetch_cache_default_ttl() -> u64 {
+        300
+    }
+
+    pub fn render_cache_max_bytes() -> usize {
+        32 * 1024 * 1024
+    }
+
+    pub fn render_cache_disk_max_bytes() -> usize {
+        256 * 1024 * 1024
+    }
+
+    pub fn asset_cache_max_bytes() -> usize {
+        64 * 1024 * 1024
+    }
+
+    pub fn decoded_cache_max_bytes() -> usize {
+        128 * 1024 * 1024
+    }
+
+    pub fn asset_cache_ttl() -> u64 {
+        3600
+    }
+
+    pub fn collage_max_images() -> usize {
+        16
+    }
+}
@@ -0,0 +1,455 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use reqwest::{header, redirect::Policy, Response, StatusCode, Url};
+use tokio::time::timeout;
+
+use super::config::ServerConfig;
+use crate::errors::Errors;
+
+/// Maps error statuses of an image source to `Errors`.
+pub fn check_status(response: &Response) -> Result<(), Errors> {
+    let header = |name: &str| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+    };
+    let status = response.status();
+    // GitHub answers 403 once the rate limit is used up
+    let rate_limited = status == StatusCode::TOO_MANY_REQUESTS
+        || (status == StatusCode::FORBIDDEN && header("x-ratelimit-remaining") == Some("0"));
+
+    if status.is_success() {
+        Ok(())
+    } else if rate_limited {
+        // Discord sends fractional seconds, GitHub the reset time as Unix seconds
+        let retry_after = header("retry-after")
+            .and_then(|seconds| seconds.parse::<f64>().ok())
+            .map(|seconds| seconds.ceil() as u64)
+            .or_else(|| {
+                let reset: u64 = header("x-ratelimit-reset")?.parse().ok()?;
+                let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+                Some(reset.saturating_sub(now.as_secs()))
+            });
+        Err(Errors::UpstreamRateLimited(retry_after))
+    } else if matches!(status, StatusCode::NOT_FOUND | StatusCode::GONE) {
+        Err(Errors::NotFound(
+            "The image source has no such image".into(),
+        ))
+    } else {
+        Err(Errors::UpstreamStatus(status.as_u16()))
+    }
+}
+
+/// Reads an image response body, failing as soon as it grows past `max_bytes`.
+/// Error statuses and non image content types are rejected up front.
+pub async fn read_limited(mut response: Response, max_bytes: usize) -> Result<Vec<u8>, Errors> {
+    check_status(&response)?;
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    // Some hosts serve every file as a generic binary
+    if !(content_type.starts_with("image/") || content_type == "application/octet-stream") {
+        return Err(Errors::InvalidInput(format!(
+            "Expected an image, got {:?}",
+            content_type
+        )));
+    }
+
+    if response.content_length().unwrap_or(0) > max_bytes as u64 {
+        return Err(Errors::TooLarge(max_bytes));
+    }
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if bytes.len() + chunk.len() > max_bytes {
+            return Err(Errors::TooLarge(max_bytes));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes)
+}
+
+/// Downloads a client supplied URL. Every hop of a redirect chain is checked
+/// against the host lists and has to resolve to public addresses only, the
+/// connection is then pinned to the checked address so DNS can't change
+/// its answer in between.
+pub async fn fetch_public_url(url: &str, config: &ServerConfig) -> Result<Vec<u8>, Errors> {
+    let limits = &config.sources.url;
+    timeout(limits.timeout(), follow_redirects(url, config, is_public))
+        .await
+        .map_err(|_| Errors::UpstreamTimeout)?
+}
+
+/// `allowed` decides which addresses may be connected to, it's only
+/// loosened by the tests to reach their local server.
+async fn follow_redirects(
+    url: &str,
+    config: &ServerConfig,
+    allowed: fn(IpAddr) -> bool,
+) -> Result<Vec<u8>, Errors> {
+    let mut url = Url::parse(url).map_err(|_| Errors::InvalidInput("Invalid URL".into()))?;
+
+    for _ in 0..=config.url_max_redirects {
+        let address = check_url(&url, config, allowed).await?;
+        let host = url.host_str().unwrap().to_string();
+        let client = reqwest::ClientBuilder::new()
+            .user_agent("My user agent")
+            .no_proxy()
+            .redirect(Policy::none())
+            .resolve(&host, address)
+            .build()?;
+        let response = client.get(url.clone()).send().await?;
+
+        let location = response
+            .headers()
+            .get(header::LOCATION)
+            .and_then(|location| location.to_str().ok());
+        match (response.status(), location) {
+            (
+                StatusCode::MOVED_PERMANENTLY
+                | StatusCode::FOUND
+                | StatusCode::SEE_OTHER
+                | StatusCode::TEMPORARY_REDIRECT
+                | StatusCode::PERMANENT_REDIRECT,
+                Some(location),
+            ) => {
+                url = url
+                    .join(location)
+                    .map_err(|_| Errors::InvalidInput("Invalid redirect location".into()))?;
+            }
+            _ => return read_limited(response, config.sources.url.max_bytes).await,
+        }
+    }
+    Err(Errors::InvalidInput("Too many redirects".into()))
+}
+
+/// Validates the scheme and host of `url` and returns the address to connect to.
+async fn check_url(
+    url: &Url,
+    config: &ServerConfig,
+    allowed: fn(IpAddr) -> bool,
+) -> Result<SocketAddr, Errors> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(Errors::InvalidInput(
+            "Only http and https URLs are supported".into(),
+        ));
+    }
+    let host = url
+        .host_str()
+        .ok_or_else(|| Errors::InvalidInput("URL has no host".into()))?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_ascii_lowercase();
+
+    let matches = |pattern: &String| {
+        let pattern = pattern.to_ascii_lowercase();
+        host == pattern || host.ends_with(&format!(".{}", pattern))
+    };
+    if config.url_denied_hosts.iter().any(matches)
+        || !(config.url_allowed_hosts.is_empty() || config.url_allowed_hosts.iter().any(matches))
+    {
+        return Err(Errors::InvalidInput(format!(
+            "Host {:?} is not allowed",
+            host
+        )));
+    }
+
+    let port = url.port_or_known_default().unwrap();
+    let addresses: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), port))
+        .await
+        .map_err(|_| Errors::InvalidInput(format!("Couldn't resolve {:?}", host)))?
+        .collect();
+    // Every address has to be public, the client could connect to any of them
+    if addresses.is_empty() || !addresses.iter().all(|address| allowed(address.ip())) {
+        return Err(Errors::InvalidInput(format!(
+            "Host {:?} doesn't resolve to a public address",
+            host
+        )));
+    }
+    Ok(addresses[0])
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public_v4(ip);
+            }
+            let segments = ip.segments();
+            // NAT64 embeds an IPv4 address in the low 32 bits
+            if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+                let [a, b] = segments[6].to_be_bytes();
+                let [c, d] = segments[7].to_be_bytes();
+                return is_public_v4(Ipv4Addr::new(a, b, c, d));
+            }
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // Unique local fc00::/7
+                || segments[0] & 0xfe00 == 0xfc00
+                // Link local fe80::/10
+                || segments[0] & 0xffc0 == 0xfe80
+                // Documentation 2001:db8::/32
+                || segments[..2] == [0x2001, 0xdb8])
+        }
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // "This network" 0.0.0.0/8
+        || a == 0
+        // Carrier grade NAT 100.64.0.0/10
+        || (a == 100 && b & 0xc0 == 64)
+        // IETF protocol assignments 192.0.0.0/24
+        || (a == 192 && b == 0 && c == 0)
+        // Benchmarking 198.18.0.0/15
+        || (a == 198 && b & 0xfe == 18)
+        // Reserved 240.0.0.0/4
+        || a >= 240)
+}
+
+#[cfg(test)]
+mod tests {
+    use figment::{
+        providers::{Format, Toml},
+        Figment,
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    /// The local server listens here, the tests treat only this address as public
+    const SERVER_IP: Ipv4Addr = Ipv4Addr::new(127, 0, 0, 2);
+
+    fn only_server(ip: IpAddr) -> bool {
+        ip == IpAddr::V4(SERVER_IP)
+    }
+
+    fn config(extra: &str) -> ServerConfig {
+        let toml = format!(
+            r#"
+            templates = []
+            default_font = "font.ttf"
+            textdraw_text_max_len = 100
+            blur_sigma = 2.0
+            colorfill_image_size = 256
+            allow_local_file_input = false
+            resize_filtertype = "triangle"
+            allow_url_input = true
+            {}
+            "#,
+            extra
+        );
+        Figment::from(Toml::string(&toml)).extract().unwrap()
+    }
+
+    /// Stand-in HTTP server answering each path with a raw response, which
+    /// may contain `{addr}` for the server's own address.
+    async fn serve(routes: Vec<(&'static str, String)>) -> SocketAddr {
+        let listener = TcpListener::bind((SERVER_IP, 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buffer = [0; 1024];
+                while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                    match stream.read(&mut buffer).await {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => request.extend_from_slice(&buffer[..n]),
+                    }
+                }
+                let request = String::from_utf8_lossy(&request);
+                let path = request.split(' ').nth(1).unwrap_or_default();
+                let response = routes
+                    .iter()
+                    .find(|(route, _)| *route == path)
+                    .map(|(_, response)| response.replace("{addr}", &addr.to_string()))
+                    .unwrap_or_else(|| "HTTP/1.1 404 Not Found\r\n\r\n".into());
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        addr
+    }
+
+    fn redirect(location: &str) -> String {
+        format!(
+            "HTTP/1.1 302 Found\r\nLocation: {}\r\nContent-Length: 0\r\n\r\n",
+            location
+        )
+    }
+
+    fn image(body: &str) -> String {
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )
+    }
+
+    async fn check(url: &str, config: &ServerConfig) -> Result<SocketAddr, Errors> {
+        check_url(&Url::parse(url).unwrap(), config, is_public).await
+    }
+
+    #[test]
+    fn rejects_non_public_addresses() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fc00::1",
+            "fe80::1",
+            // IPv4 mapped and NAT64 forms of loopback and private addresses
+            "::ffff:127.0.0.1",
+            "::ffff:10.0.0.1",
+            "64:ff9b::7f00:1",
+            "64:ff9b::a00:1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{} is not public", ip);
+        }
+        for ip in [
+            "8.8.8.8",
+            "::ffff:8.8.8.8",
+            "64:ff9b::808:808",
+            "2606:4700::1111",
+        ] {
+            assert!(is_public(ip.parse().unwrap()), "{} is public", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn check_url_rejects_non_public_hosts() {
+        let config = config("");
+        for url in [
+            "http://127.0.0.1/",
+            "http://10.0.0.1/",
+            "http://[::1]/",
+            "http://[::ffff:127.0.0.1]/",
+            "http://[64:ff9b::7f00:1]/",
+            "http://localhost/",
+            "ftp://8.8.8.8/",
+        ] {
+            assert!(check(url, &config).await.is_err(), "{} is rejected", url);
+        }
+        assert!(check("http://8.8.8.8/", &config).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn host_lists_match_subdomains() {
+        let denied = config(r#"url_denied_hosts = ["Example.com"]"#);
+        for url in ["http://example.com/", "http://cdn.EXAMPLE.com/"] {
+            assert!(check(url, &denied).await.is_err(), "{} is denied", url);
+        }
+        // Only whole labels match
+        let allowed = config(r#"url_allowed_hosts = ["8.8.8.8", "example.com"]"#);
+        assert!(check("http://8.8.8.8/", &allowed).await.is_ok());
+        for url in ["http://8.8.4.4/", "http://notexample.com/"] {
+            assert!(check(url, &allowed).await.is_err(), "{} isn't allowed", url);
+        }
+
+        let both = config(
+            r#"
+            url_allowed_hosts = ["example.com"]
+            url_denied_hosts = ["internal.example.com"]
+            "#,
+        );
+        assert!(check("http://a.internal.example.com/", &both)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn follows_redirects_and_revalidates_every_hop() {
+        let addr = serve(vec![
+            ("/start", redirect("/image")),
+            ("/image", image("png")),
+            ("/loopback", redirect("http://127.0.0.1/image")),
+            ("/absolute", redirect("http://{addr}/image")),
+        ])
+        .await;
+        let config = config("");
+        let url = |path: &str| format!("http://{}{}", addr, path);
+
+        for path in ["/start", "/absolute"] {
+            let bytes = follow_redirects(&url(path), &config, only_server).await;
+            assert_eq!(bytes.unwrap(), b"png");
+        }
+        let error = follow_redirects(&url("/loopback"), &config, only_server)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("public address"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn limits_redirects() {
+        let addr = serve(vec![
+            ("/0", redirect("/1")),
+            ("/1", redirect("/2")),
+            ("/2", redirect("/3")),
+            ("/3", image("png")),
+        ])
+        .await;
+        let url = |path: &str| format!("http://{}{}", addr, path);
+
+        let config = config("url_max_redirects = 2");
+        let bytes = follow_redirects(&url("/1"), &config, only_server).await;
+        assert_eq!(bytes.unwrap(), b"png");
+        let error = follow_redirects(&url("/0"), &config, only_server)
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Too many redirects");
+    }
+
+    #[tokio::test]
+    async fn read_limited_stops_past_max_bytes() {
+        let body = "x".repeat(4096);
+        let addr = serve(vec![
+            ("/sized", image(&body)),
+            // Without a length the body has to be counted while streaming
+            (
+                "/streamed",
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nConnection: close\r\n\r\n{}",
+                    body
+                ),
+            ),
+            (
+                "/html",
+                "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: 2\r\n\r\nhi".into(),
+            ),
+        ])
+        .await;
+        let get = |path: &str| reqwest::get(format!("http://{}{}", addr, path));
+
+        for path in ["/sized", "/streamed"] {
+            let error = read_limited(get(path).await.unwrap(), 1000).await;
+            assert!(matches!(error, Err(Errors::TooLarge(1000))), "{}", path);
+            let bytes = read_limited(get(path).await.unwrap(), 4096).await;
+            assert_eq!(bytes.unwrap().len(), 4096);
+        }
+        let error = read_limited(get("/html").await.unwrap(), 1000).await;
+        assert!(matches!(error, Err(Errors::InvalidInput(..))));
+    }
+}
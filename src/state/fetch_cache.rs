@@ -0,0 +1,281 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use reqwest::{header, header::HeaderMap, Client, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+
+use super::{download::read_limited, lru::LruCache};
+use crate::errors::Errors;
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Metadata {
+    key: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    /// Unix seconds after which the entry has to be revalidated
+    expires: u64,
+}
+
+#[derive(Clone)]
+struct CachedResponse {
+    bytes: Arc<Vec<u8>>,
+    metadata: Metadata,
+}
+
+/// Cache of remote source bytes, in memory and optionally on disk.
+/// Freshness follows the `Cache-Control` header of the response, stale
+/// entries are revalidated with `If-None-Match` / `If-Modified-Since`.
+pub struct FetchCache {
+    memory: Mutex<LruCache<String, CachedResponse>>,
+    directory: Option<PathBuf>,
+    /// `.bin`/`.json` pairs in `directory` by file stem
+    disk: Mutex<LruCache<String, ()>>,
+    default_ttl: u64,
+}
+
+impl FetchCache {
+    pub fn new(
+        max_bytes: usize,
+        directory: Option<PathBuf>,
+        disk_max_bytes: usize,
+        default_ttl: u64,
+    ) -> Self {
+        let mut disk = LruCache::new(disk_max_bytes);
+        if let Some(directory) = &directory {
+            std::fs::create_dir_all(directory).expect("Couldn't create the fetch cache directory");
+            // Responses from earlier runs, oldest first so they're evicted first
+            let mut files: HashMap<String, (SystemTime, usize)> = HashMap::new();
+            for entry in std::fs::read_dir(directory)
+                .expect("Couldn't read the fetch cache directory")
+                .flatten()
+            {
+                let path = entry.path();
+                let (stem, metadata) = match (path.file_stem(), entry.metadata()) {
+                    (Some(stem), Ok(metadata)) => (stem.to_string_lossy().into_owned(), metadata),
+                    _ => continue,
+                };
+                let modified = metadata.modified().unwrap_or(UNIX_EPOCH);
+                let file = files.entry(stem).or_insert((modified, 0));
+                file.0 = file.0.max(modified);
+                file.1 += metadata.len() as usize;
+            }
+            let mut files: Vec<_> = files.into_iter().collect();
+            files.sort_by_key(|(_, (modified, _))| *modified);
+            for (stem, (_, size)) in files {
+                for evicted in disk.insert(stem, (), size) {
+                    Self::remove_files(directory, &evicted);
+                }
+            }
+        }
+
+        Self {
+            memory: Mutex::new(LruCache::new(max_bytes)),
+            directory,
+            disk: Mutex::new(disk),
+            default_ttl,
+        }
+    }
+
+    fn remove_files(directory: &Path, stem: &str) {
+        let path = directory.join(stem);
+        let _ = std::fs::remove_file(path.with_extension("bin"));
+        let _ = std::fs::remove_file(path.with_extension("json"));
+    }
+
+    /// GETs `url`, answering from the cache while the stored response is fresh.
+    pub async fn fetch(
+        &self,
+        client: &Client,
+        url: &str,
+        size: u32,
+        max_bytes: usize,
+    ) -> Result<Vec<u8>, Errors> {
+        let key = Self::key(url, size)?;
+        let cached = self.memory.lock().unwrap().get(&key).cloned();
+        let cached = match cached {
+            Some(cached) => Some(cached),
+            None => self.read_disk(&key).await,
+        };
+
+        if let Some(cached) = &cached {
+            if cached.metadata.expires > now() {
+                self.store_memory(cached.clone());
+                return Ok(cached.bytes.to_vec());
+            }
+        }
+
+        let mut request = client.get(url);
+        if let Some(cached) = &cached {
+            if let Some(etag) = &cached.metadata.etag {
+                request = request.header(header::IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &cached.metadata.last_modified {
+                request = request.header(header::IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+        let response = request.send().await?;
+
+        if let (StatusCode::NOT_MODIFIED, Some(mut cached)) = (response.status(), cached) {
+            let bytes = cached.bytes.to_vec();
+            match self.expiry(response.headers()) {
+                Some(expires) => {
+                    cached.metadata.expires = expires;
+                    self.store(cached).await;
+                }
+                None => self.remove(&key).await,
+            }
+            return Ok(bytes);
+        }
+        let expires = self.expiry(response.headers());
+        let header = |name: header::HeaderName| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(String::from)
+        };
+        let (etag, last_modified) = (header(header::ETAG), header(header::LAST_MODIFIED));
+        let bytes = read_limited(response, max_bytes).await?;
+
+        if let Some(expires) = expires {
+            self.store(CachedResponse {
+                bytes: Arc::new(bytes.clone()),
+                metadata: Metadata {
+                    key,
+                    etag,
+                    last_modified,
+                    expires,
+                },
+            })
+            .await;
+        }
+        Ok(bytes)
+    }
+
+    /// Normalized URL plus the requested size. Host case, default ports,
+    /// query order and fragments don't produce separate entries.
+    fn key(url: &str, size: u32) -> Result<String, Errors> {
+        let mut url =
+            Url::parse(url).map_err(|_| Errors::InvalidInput(format!("Invalid URL: {}", url)))?;
+        let mut query: Vec<_> = url.query_pairs().into_owned().collect();
+        query.sort();
+        if query.is_empty() {
+            url.set_query(None);
+        } else {
+            url.query_pairs_mut().clear().extend_pairs(query);
+        }
+        url.set_fragment(None);
+        Ok(format!("{} {}", url, size))
+    }
+
+    /// Unix time the response goes stale, `None` when it mustn't be stored.
+    fn expiry(&self, headers: &HeaderMap) -> Option<u64> {
+        let mut ttl = self.default_ttl;
+        let cache_control = headers
+            .get_all(header::CACHE_CONTROL)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|directive| directive.trim().to_ascii_lowercase());
+        for directive in cache_control {
+            match directive.split_once('=') {
+                Some(("max-age", seconds)) => {
+                    ttl = seconds.trim_matches('"').parse().unwrap_or(0);
+                }
+                None if directive == "no-store" => return None,
+                None if directive == "no-cache" => ttl = 0,
+                _ => {}
+            }
+        }
+        Some(now() + ttl)
+    }
+
+    async fn store(&self, cached: CachedResponse) {
+        self.store_memory(cached.clone());
+        let directory = match &self.directory {
+            Some(directory) => directory,
+            None => return,
+        };
+        // Best effort, a failed write only costs a download later
+        let stem = Self::stem(&cached.metadata.key);
+        let path = directory.join(&stem);
+        let metadata = serde_json::to_vec(&cached.metadata).unwrap();
+        let size = cached.bytes.len() + metadata.len();
+        if tokio::fs::write(path.with_extension("bin"), &*cached.bytes)
+            .await
+            .is_ok()
+            && tokio::fs::write(path.with_extension("json"), metadata)
+                .await
+                .is_ok()
+        {
+            let evicted = self.disk.lock().unwrap().insert(stem, (), size);
+            for stem in evicted {
+                Self::remove_disk_files(directory, &stem).await;
+            }
+        }
+    }
+
+    /// Drops the entry from both tiers.
+    async fn remove(&self, key: &str) {
+        self.memory.lock().unwrap().remove(key);
+        let directory = match &self.directory {
+            Some(directory) => directory,
+            None => return,
+        };
+        let stem = Self::stem(key);
+        if self.disk.lock().unwrap().remove(&stem).is_some() {
+            Self::remove_disk_files(directory, &stem).await;
+        }
+    }
+
+    async fn remove_disk_files(directory: &Path, stem: &str) {
+        let path = directory.join(stem);
+        let _ = tokio::fs::remove_file(path.with_extension("bin")).await;
+        let _ = tokio::fs::remove_file(path.with_extension("json")).await;
+    }
+
+    fn store_memory(&self, cached: CachedResponse) {
+        let size = cached.bytes.len();
+        self.memory
+            .lock()
+            .unwrap()
+            .insert(cached.metadata.key.clone(), cached, size);
+    }
+
+    async fn read_disk(&self, key: &str) -> Option<CachedResponse> {
+        let stem = Self::stem(key);
+        self.disk.lock().unwrap().get(&stem)?;
+        let path = self.directory.as_ref()?.join(stem);
+        let metadata: Metadata =
+            serde_json::from_slice(&tokio::fs::read(path.with_extension("json")).await.ok()?)
+                .ok()?;
+        // Hash collisions are possible, the stored key settles it
+        if metadata.key != key {
+            return None;
+        }
+        let bytes = tokio::fs::read(path.with_extension("bin")).await.ok()?;
+        Some(CachedResponse {
+            bytes: Arc::new(bytes),
+            metadata,
+        })
+    }
+
+    fn stem(key: &str) -> String {
+        // FNV-1a, stable across builds unlike the std hasher
+        let hash = key.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+        format!("{:016x}", hash)
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
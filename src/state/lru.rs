@@ -0,0 +1,107 @@
+use std::{
+    borrow::Borrow,
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+};
+
+struct Entry<V> {
+    value: V,
+    size: usize,
+    tick: u64,
+}
+
+/// Least recently used cache bounded by the total size of its values.
+/// Sizes are supplied by the caller, usually the byte length of the value.
+pub struct LruCache<K, V> {
+    entries: HashMap<K, Entry<V>>,
+    /// Last use tick to key, the first entry is the least recently used
+    order: BTreeMap<u64, K>,
+    tick: u64,
+    size: usize,
+    max_size: usize,
+}
+
+impl<K: Hash + Eq + Clone, V> LruCache<K, V> {
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+            size: 0,
+            max_size,
+        }
+    }
+
+    /// Returns the value and marks it as recently used.
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.tick += 1;
+        let entry = self.entries.get_mut(key)?;
+        let key = self.order.remove(&entry.tick).unwrap();
+        entry.tick = self.tick;
+        self.order.insert(self.tick, key);
+        Some(&entry.value)
+    }
+
+    /// Inserts or replaces a value, evicting the least recently used entries
+    /// until everything fits. Values larger than the whole cache aren't stored.
+    /// Returns the keys that are no longer stored, including `key` if it didn't fit.
+    pub fn insert(&mut self, key: K, value: V, size: usize) -> Vec<K> {
+        self.remove(&key);
+        if size > self.max_size {
+            return vec![key];
+        }
+        let mut evicted = vec![];
+        while self.size + size > self.max_size {
+            let (_, oldest) = self.order.pop_first().unwrap();
+            self.size -= self.entries.remove(&oldest).unwrap().size;
+            evicted.push(oldest);
+        }
+
+        self.tick += 1;
+        self.order.insert(self.tick, key.clone());
+        self.entries.insert(
+            key,
+            Entry {
+                value,
+                size,
+                tick: self.tick,
+            },
+        );
+        self.size += size;
+        evicted
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let entry = self.entries.remove(key)?;
+        self.order.remove(&entry.tick);
+        self.size -= entry.size;
+        Some(entry.value)
+    }
+
+    /// Entries with their sizes, least recently used first.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V, usize)> {
+        self.order.values().map(move |key| {
+            let entry = &self.entries[key];
+            (key, &entry.value, entry.size)
+        })
+    }
+
+    /// Total size of the stored values.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+        self.size = 0;
+    }
+}
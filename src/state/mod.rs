@@ -1,3 +1,7 @@
-mod asset_cache;
-mod config;
+pub mod asset_cache;
+pub mod config;
+pub mod download;
+mod fetch_cache;
+mod lru;
+pub mod render_cache;
 pub mod serverstate;
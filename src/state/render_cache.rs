@@ -0,0 +1,158 @@
+use std::{
+    fmt::Debug,
+    future::Future,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use image::DynamicImage;
+use tokio::task::spawn_blocking;
+
+use super::lru::LruCache;
+use crate::{
+    datastructures::image::Resolved,
+    errors::Errors,
+    imagelib::image_response::{encode_png, CachedImageResponse},
+};
+
+const FNV_OFFSET: u128 = 0x6c62272e07bb014262b821756295c58d;
+const FNV_PRIME: u128 = 0x0000000001000000000000000000013b;
+
+/// FNV-1a 128 of `bytes` as 32 hex digits.
+pub fn hash_bytes(bytes: &[u8]) -> String {
+    format!("{:032x}", RenderKey(FNV_OFFSET).field(bytes).0)
+}
+
+/// Canonical hash of everything a render depends on. Every field is
+/// length prefixed so neighbouring fields can't run into each other.
+#[derive(Clone, Copy)]
+pub struct RenderKey(u128);
+
+impl RenderKey {
+    fn field(mut self, bytes: &[u8]) -> Self {
+        for byte in (bytes.len() as u64).to_le_bytes().iter().chain(bytes) {
+            self.0 = (self.0 ^ *byte as u128).wrapping_mul(FNV_PRIME);
+        }
+        self
+    }
+
+    /// Adds a parameter, after defaults and validation have been applied.
+    pub fn param(self, name: &str, value: impl Debug) -> Self {
+        self.field(name.as_bytes())
+            .field(format!("{:?}", value).as_bytes())
+    }
+
+    pub fn input(self, source: &Resolved) -> Self {
+        self.field(&source.fingerprint())
+    }
+
+    pub fn finish(self) -> String {
+        format!("{:032x}", self.0)
+    }
+}
+
+/// Encoded output of deterministic renders, kept in memory and optionally
+/// in a size bounded directory.
+pub struct RenderCache {
+    /// Mixed into every key so config changes don't serve old renders
+    seed: RenderKey,
+    memory: Mutex<LruCache<String, Arc<Vec<u8>>>>,
+    directory: Option<PathBuf>,
+    /// Files in `directory` by key
+    disk: Mutex<LruCache<String, ()>>,
+}
+
+impl RenderCache {
+    pub fn new(
+        seed: &[u8],
+        max_bytes: usize,
+        directory: Option<PathBuf>,
+        disk_max_bytes: usize,
+    ) -> Self {
+        let mut disk = LruCache::new(disk_max_bytes);
+        if let Some(directory) = &directory {
+            std::fs::create_dir_all(directory).expect("Couldn't create the render cache directory");
+            // Renders from earlier runs, oldest first so they're evicted first
+            let mut files: Vec<_> = std::fs::read_dir(directory)
+                .expect("Couldn't read the render cache directory")
+                .filter_map(|entry| {
+                    let entry = entry.ok()?;
+                    let metadata = entry.metadata().ok()?;
+                    let name = entry.file_name().into_string().ok()?;
+                    Some((metadata.modified().ok()?, name, metadata.len() as usize))
+                })
+                .collect();
+            files.sort();
+            for (_, name, size) in files {
+                for evicted in disk.insert(name, (), size) {
+                    let _ = std::fs::remove_file(directory.join(evicted));
+                }
+            }
+        }
+
+        Self {
+            seed: RenderKey(FNV_OFFSET).field(seed),
+            memory: Mutex::new(LruCache::new(max_bytes)),
+            directory,
+            disk: Mutex::new(disk),
+        }
+    }
+
+    /// Starts the key of a render of `route`.
+    pub fn key(&self, route: &str) -> RenderKey {
+        self.seed.field(route.as_bytes())
+    }
+
+    pub async fn get(&self, key: &str) -> Option<Arc<Vec<u8>>> {
+        let cached = self.memory.lock().unwrap().get(key).cloned();
+        if cached.is_some() {
+            return cached;
+        }
+
+        let directory = self.directory.as_ref()?;
+        self.disk.lock().unwrap().get(key)?;
+        let bytes = Arc::new(tokio::fs::read(directory.join(key)).await.ok()?);
+        self.memory
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), Arc::clone(&bytes), bytes.len());
+        Some(bytes)
+    }
+
+    pub async fn insert(&self, key: String, bytes: Arc<Vec<u8>>) {
+        self.memory
+            .lock()
+            .unwrap()
+            .insert(key.clone(), Arc::clone(&bytes), bytes.len());
+
+        if let Some(directory) = &self.directory {
+            // Best effort, a failed write only costs a render later
+            if tokio::fs::write(directory.join(&key), &*bytes)
+                .await
+                .is_ok()
+            {
+                let evicted = self.disk.lock().unwrap().insert(key, (), bytes.len());
+                for key in evicted {
+                    let _ = tokio::fs::remove_file(directory.join(key)).await;
+                }
+            }
+        }
+    }
+
+    /// Serves the stored render for `key`, or awaits `render` and stores it.
+    pub async fn get_or_render(
+        &self,
+        key: RenderKey,
+        render: impl Future<Output = Result<DynamicImage, Errors>>,
+    ) -> Result<CachedImageResponse, Errors> {
+        let key = key.finish();
+        if let Some(bytes) = self.get(&key).await {
+            return Ok(CachedImageResponse { etag: key, bytes });
+        }
+
+        let image = render.await?;
+        let bytes = Arc::new(spawn_blocking(move || encode_png(&image)).await??);
+        self.insert(key.clone(), Arc::clone(&bytes)).await;
+        Ok(CachedImageResponse { etag: key, bytes })
+    }
+}
@@ -1,16 +1,29 @@
-use super::{asset_cache::AssetCache, config::ServerConfig};
+use std::{path::PathBuf, time::Duration};
+
+use super::{
+    asset_cache::AssetCache,
+    config::{ServerConfig, SourceLimits},
+    fetch_cache::FetchCache,
+    render_cache::RenderCache,
+};
+use tokio::time::timeout;
+
+use crate::errors::Errors;
 
 pub struct ServerState {
     #[cfg(feature = "redis_ratelimit")]
     redis_client: redis::Client,
     http_client: reqwest::Client,
+    fetch_cache: FetchCache,
     pub cache: AssetCache,
+    pub render_cache: RenderCache,
     pub config: ServerConfig,
 }
 
 impl ServerState {
     pub fn new(config_filename: &str) -> Self {
-        Self {
+        let config = ServerConfig::new(config_filename);
+        let state = Self {
             #[cfg(feature = "redis_ratelimit")]
             redis_client: redis::Client::open(
                 std::env::var("REDIS_URI").expect("Couldn't find REDIS_URI"),
@@ -20,8 +33,46 @@ impl ServerState {
                 .user_agent("My user agent")
                 .build()
                 .unwrap(),
-            cache: AssetCache::new(),
-            config: ServerConfig::new(config_filename),
+            fetch_cache: FetchCache::new(
+                config.fetch_cache_max_bytes,
+                config.fetch_cache_dir.as_ref().map(PathBuf::from),
+                config.fetch_cache_disk_max_bytes,
+                config.fetch_cache_default_ttl,
+            ),
+            render_cache: RenderCache::new(
+                &std::fs::read(config_filename).unwrap_or_default(),
+                config.render_cache_max_bytes,
+                config.render_cache_dir.as_ref().map(PathBuf::from),
+                config.render_cache_disk_max_bytes,
+            ),
+            cache: AssetCache::new(
+                config.asset_cache_max_bytes,
+                config.decoded_cache_max_bytes,
+                Duration::from_secs(config.asset_cache_ttl),
+            ),
+            config,
+        };
+        state.preload_assets();
+        state
+    }
+
+    /// Reads every template's start file and fonts into the asset cache.
+    fn preload_assets(&self) {
+        let default_font = &self.config.default_font;
+        for template in self.config.templates.iter() {
+            self.cache
+                .preload_image(template.startfile())
+                .and_then(|_| {
+                    template
+                        .fonts(default_font)
+                        .try_for_each(|font| self.cache.get_font(font).map(drop))
+                })
+                .unwrap_or_else(|error| {
+                    panic!(
+                        "Couldn't load assets of template {:?}: {}",
+                        template.name, error
+                    )
+                });
         }
     }
 
@@ -29,6 +80,22 @@ impl ServerState {
         &self.http_client
     }
 
+    /// Downloads `url` through the fetch cache within the source's limits.
+    /// `size` is the image size the URL was built for.
+    pub async fn fetch(
+        &self,
+        url: &str,
+        size: u32,
+        limits: &SourceLimits,
+    ) -> Result<Vec<u8>, Errors> {
+        let fetch = self
+            .fetch_cache
+            .fetch(&self.http_client, url, size, limits.max_bytes);
+        timeout(limits.timeout(), fetch)
+            .await
+            .map_err(|_| Errors::UpstreamTimeout)?
+    }
+
     #[cfg(feature = "ratelimit")]
     pub fn redis(&self) -> Result<redis::Connection, crate::errors::Errors> {
         Ok(self.redis_client.get_connection()?)